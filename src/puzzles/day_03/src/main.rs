@@ -1,25 +1,44 @@
+use std::fs;
 use utils::read_lines;
+
+const SLOPES: [(usize, usize); 5] = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
+
+// Symbols used to draw each overlaid slope as (open square, tree hit)
+const MARKERS: [(char, char); 5] = [('O', 'X'), ('o', 'x'), ('0', '*'), ('@', '&'), ('+', '%')];
+
 fn main() {
     let lines = read_lines("src/inputs/day_03.txt")
         .unwrap()
-        .flatten()
+        .map_while(Result::ok)
         .collect::<Vec<_>>();
 
+    // `day_03 render [FILE]` draws the traversed paths instead of solving
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("render") {
+        let map = render_paths(&lines, &SLOPES);
+        match args.next() {
+            Some(path) => fs::write(path, map).unwrap(),
+            None => print!("{}", map),
+        }
+        return;
+    }
+
     let p1 = count_trees(&lines, 3, 1);
     println!("Part 1: {:?}", p1);
 
-    let p2 = multiple_tree_count(&lines, &[(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)]);
+    let p2 = multiple_tree_count(&lines, &SLOPES);
     println!("Part 2: {:?}", p2);
 }
 
+#[allow(clippy::ptr_arg)]
 fn count_trees<S: AsRef<str>>(lines: &Vec<S>, slope_h: usize, slope_v: usize) -> usize {
     let width = lines[0].as_ref().len();
     let (count, _) = lines
-        .into_iter()
+        .iter()
         .enumerate()
         .filter(|&(i, _)| i % slope_v == 0)
         .fold((0, 0), |(count, col), (_, line)| {
-            let level = line.as_ref().chars().into_iter().collect::<Vec<_>>();
+            let level = line.as_ref().chars().collect::<Vec<_>>();
             let maybe_tree = level[col % width];
             let new_count = if maybe_tree == '#' { count + 1 } else { count };
             (new_count, col + slope_h)
@@ -29,11 +48,43 @@ fn count_trees<S: AsRef<str>>(lines: &Vec<S>, slope_h: usize, slope_v: usize) ->
 }
 
 fn multiple_tree_count<S: AsRef<str>>(lines: &Vec<S>, slopes: &[(usize, usize)]) -> usize {
-    slopes.into_iter().fold(1, |acc, (slope_h, slope_v)| {
+    slopes.iter().fold(1, |acc, (slope_h, slope_v)| {
         acc * count_trees(lines, *slope_h, *slope_v)
     })
 }
 
+fn render_paths<S: AsRef<str>>(lines: &[S], slopes: &[(usize, usize)]) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+    let width = lines[0].as_ref().len();
+    let reach = slopes
+        .iter()
+        .map(|(slope_h, slope_v)| (lines.len() - 1) / slope_v * slope_h)
+        .max()
+        .unwrap_or(0);
+    let tiles = reach / width + 1;
+
+    let mut grid = lines
+        .iter()
+        .map(|line| line.as_ref().repeat(tiles).chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    // Draw in reverse so the first slope ends up on top where paths cross
+    for (i, (slope_h, slope_v)) in slopes.iter().enumerate().rev() {
+        let (open, tree) = MARKERS[i % MARKERS.len()];
+        for (step, row) in (0..lines.len()).step_by(*slope_v).enumerate() {
+            let col = step * slope_h;
+            let is_tree = lines[row].as_ref().as_bytes()[col % width] == b'#';
+            grid[row][col] = if is_tree { tree } else { open };
+        }
+    }
+
+    grid.into_iter()
+        .map(|row| row.into_iter().chain(Some('\n')).collect::<String>())
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -84,4 +135,31 @@ mod test {
             336
         );
     }
+
+    #[test]
+    fn render_example_p1() {
+        let rendered = render_paths(&INPUT, &[(3, 1)]);
+        let expected = [
+            "O.##.........##.........##.......",
+            "#..O#...#..#...#...#..#...#...#..",
+            ".#....X..#..#....#..#..#....#..#.",
+            "..#.#...#O#..#.#...#.#..#.#...#.#",
+            ".#...##..#..X...##..#..#...##..#.",
+            "..#.##.......#.X#.......#.##.....",
+            ".#.#.#....#.#.#.#.O..#.#.#.#....#",
+            ".#........#.#........X.#........#",
+            "#.##...#...#.##...#...#.X#...#...",
+            "#...##....##...##....##...#X....#",
+            ".#..#...#.#.#..#...#.#.#..#...X.#",
+        ];
+        assert_eq!(rendered.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn render_overlaid_slopes() {
+        let rendered = render_paths(&INPUT, &[(3, 1), (1, 1)]);
+        assert_eq!(rendered.matches('X').count(), 7);
+        assert_eq!(rendered.matches('x').count(), 2);
+        assert!(rendered.starts_with('O'));
+    }
 }
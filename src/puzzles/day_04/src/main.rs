@@ -1,154 +1,452 @@
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use std::marker::PhantomData;
-use std::{borrow::Borrow, collections::HashMap};
+mod schema;
+
+use schema::{Problem, Record, Schema, SchemaError, Violation};
+use serde::Serialize;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use utils::export::Format;
 use utils::read_lines;
 
 fn main() {
     let lines = read_lines("src/inputs/day_04.txt")
         .unwrap()
-        .flatten()
+        .map_while(Result::ok)
         .collect::<Vec<_>>();
 
-    let result = count_valid(&lines);
-    println!("Part: {:?}", result);
+    // `day_04 --schema FILE` swaps the built-in rules for a JSON schema
+    let args = std::env::args().collect::<Vec<_>>();
+    let schema = match args.iter().position(|a| a == "--schema") {
+        Some(i) => passport_schema(fs::read_to_string(&args[i + 1]).unwrap()).unwrap(),
+        None => Schema::passport(),
+    };
+
+    // `day_04 --report` lists why each rejected passport failed
+    if args.iter().any(|a| a == "--report") {
+        let diagnostics = diagnose(&lines, &schema);
+        for d in &diagnostics {
+            println!("{}", d);
+        }
+        println!();
+        for (cause, count) in summarize(&diagnostics) {
+            println!("{:>5}  {}", count, cause);
+        }
+        return;
+    }
+
+    // `day_04 --export jsonl|csv [--out FILE] [--rejected FILE]` writes the
+    // normalized valid passports, and optionally the rejected ones
+    if let Some(i) = args.iter().position(|a| a == "--export") {
+        let format = args[i + 1].parse::<Format>().unwrap();
+        let path_after = |flag: &str| {
+            let i = args.iter().position(|a| a == flag)?;
+            args.get(i + 1)
+        };
+        let (valid, rejected) = sort_passports(&lines, &schema);
+        match path_after("--out") {
+            Some(path) => format.write(File::create(path).unwrap(), &valid),
+            None => format.write(io::stdout(), &valid),
+        }
+        .unwrap();
+        if let Some(path) = path_after("--rejected") {
+            format
+                .write(File::create(path).unwrap(), &rejected)
+                .unwrap();
+        }
+        return;
+    }
+
+    let result = count_present(&lines);
+    println!("Part 1: {:?}", result);
+
+    let result = count_valid(&lines, &schema);
+    println!("Part 2: {:?}", result);
+}
+
+// Validation stages. Each one decides how the passport fields are stored, so a
+// passport can only reach a later stage through the transition methods below.
+pub trait Stage {
+    type Year;
+    type Height;
+    type Color;
+    type Field;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Unvalidated;
+#[derive(Debug, PartialEq)]
+pub struct FieldsPresent;
+#[derive(Debug, PartialEq)]
+pub struct Validated;
+#[derive(Debug, PartialEq)]
+pub struct Normalized;
+
+impl Stage for Unvalidated {
+    type Year = Option<String>;
+    type Height = Option<String>;
+    type Color = Option<String>;
+    type Field = Option<String>;
+}
+impl Stage for FieldsPresent {
+    type Year = String;
+    type Height = String;
+    type Color = String;
+    type Field = String;
 }
-#[allow(dead_code)]
-enum EnumPassportParsed {
-    Parsed,
+// Exactly what the schema approved
+impl Stage for Validated {
+    type Year = String;
+    type Height = String;
+    type Color = String;
+    type Field = String;
 }
-#[allow(dead_code)]
-enum EnumPassportUnparsed {
-    Unparsed,
+impl Stage for Normalized {
+    type Year = Normal<u32>;
+    type Height = Normal<Height>;
+    type Color = Normal<Rgb>;
+    type Field = String;
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-pub struct Passport<A> {
-    #[serde(skip)]
-    __status: PhantomData<A>,
-    byr: String,
-    iyr: String,
-    eyr: String,
-    hgt: String,
-    hcl: String,
-    ecl: String,
-    pid: String,
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Passport<S: Stage> {
+    byr: S::Year,
+    iyr: S::Year,
+    eyr: S::Year,
+    hgt: S::Height,
+    hcl: S::Color,
+    ecl: S::Field,
+    pid: S::Field,
     cid: Option<String>,
 }
 
-type PassportParsed = Passport<EnumPassportParsed>;
-type PassportUnparsed = Passport<EnumPassportUnparsed>;
+// A schema may accept values the built-in conversions don't understand, like
+// heights in mm, so those are kept as written
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Normal<T> {
+    Parsed(T),
+    Raw(String),
+}
 
-impl Passport<PassportParsed> {
-    // Part 1
-    pub fn custom_deserialize<T: AsRef<str>>(str: T) -> Option<PassportParsed> {
-        str.as_ref()
-            .split(|c| c == ' ' || c == '\n')
-            .map(|field| -> Option<(&str, &str)> {
-                let mut it_split = field.split(":");
-                let key = it_split.next()?;
-                let value = it_split.next()?;
-                Some((key, value))
-            })
-            .collect::<Option<HashMap<_, _>>>()
-            .and_then(|values| -> Option<PassportUnparsed> {
-                let json_str = serde_json::to_string(&values).ok()?;
-                let result: PassportUnparsed = serde_json::from_str(&json_str).ok()?;
-                Some(result)
-            })
-            .and_then(|unparsed| -> Option<PassportParsed> {
-                Some(PassportParsed {
-                    __status: PhantomData,
-                    byr: bound_numeric_str(unparsed.byr, 1920, 2002)?,
-                    iyr: bound_numeric_str(unparsed.iyr, 2010, 2020)?,
-                    eyr: bound_numeric_str(unparsed.eyr, 2020, 2030)?,
-                    hgt: Passport::hgt(unparsed.hgt)?,
-                    hcl: Passport::parse_hcl(unparsed.hcl)?,
-                    ecl: Passport::parse_ecl(unparsed.ecl)?,
-                    pid: Passport::parse_pid(unparsed.pid)?,
-                    cid: unparsed.cid,
-                })
-            })
+impl<T> Normal<T> {
+    fn new(raw: String, parse: fn(&str) -> Option<T>) -> Self {
+        match parse(&raw) {
+            Some(value) => Normal::Parsed(value),
+            None => Normal::Raw(raw),
+        }
     }
+}
 
-    pub fn from_multiline_input_p1<T: AsRef<str> + Borrow<str>>(
-        arr: &Vec<T>,
-    ) -> Vec<Option<PassportParsed>> {
-        arr.split(|s| s.as_ref() == "")
-            .map(|s| Passport::custom_deserialize(s.join("\n")))
-            .collect::<Vec<_>>()
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Height {
+    cm: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Rgb {
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+fn parse_year(str: &str) -> Option<u32> {
+    str.parse::<u32>().ok()
+}
+
+fn parse_height(str: &str) -> Option<Height> {
+    let split = str.find(|c: char| !c.is_ascii_digit()).unwrap_or(str.len());
+    let (n, unit) = str.split_at(split);
+    let n = n.parse::<f64>().ok()?;
+    match unit {
+        "cm" => Some(Height { cm: n }),
+        "in" => Some(Height {
+            cm: (n * 254.0).round() / 100.0,
+        }),
+        _ => None,
     }
+}
 
-    fn hgt<T: AsRef<str>>(__str: T) -> Option<T> {
-        let str = __str.as_ref();
-        let re_cm = Regex::new(r#"^([0-9]+)cm$"#).unwrap();
-        let re_in = Regex::new(r#"^([0-9]+)in$"#).unwrap();
+fn parse_color(str: &str) -> Option<Rgb> {
+    let hex = str.strip_prefix('#').filter(|h| h.len() == 6)?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(Rgb {
+        r: channel(0)?,
+        g: channel(2)?,
+        b: channel(4)?,
+    })
+}
 
-        if re_cm.is_match(str.as_ref()) {
-            let caps = re_cm.captures_iter(str.as_ref()).collect::<Vec<_>>();
-            let n = &caps[0][1].parse::<u32>().ok()?;
-            if *n >= 150 && *n <= 193 {
-                Some(__str)
-            } else {
-                None
-            }
-        } else if re_in.is_match(str.as_ref()) {
-            let caps = re_in.captures_iter(str.as_ref()).collect::<Vec<_>>();
-            let n = &caps[0][1].parse::<u32>().ok()?;
-            if *n >= 59 && *n <= 76 {
-                Some(__str)
-            } else {
-                None
+// Fields `check_fields` insists on, so a schema has to require them too or
+// the stages would disagree with the schema about what's missing
+const REQUIRED: [&str; 7] = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
+
+pub fn passport_schema<T: AsRef<str>>(json: T) -> Result<Schema, SchemaError> {
+    Schema::from_json(json)?.require(&REQUIRED)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    line: usize,
+    violation: Violation,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.violation)
+    }
+}
+
+fn parse_record<T: AsRef<str>>(str: T) -> (Record, Vec<Violation>) {
+    let mut record = Record::new();
+    let mut violations = vec![];
+    for field in str.as_ref().split([' ', '\n']).filter(|f| !f.is_empty()) {
+        match field.split_once(':') {
+            Some((key, value)) => {
+                record.insert(key.to_string(), value.to_string());
             }
-        } else {
-            None
+            None => violations.push(Violation::new(field, None, Problem::Malformed)),
         }
     }
+    (record, violations)
+}
 
-    pub fn parse_hcl<T: AsRef<str>>(str: T) -> Option<T> {
-        let re = Regex::new(r#"^#[a-fA-F0-9]{6}$"#).unwrap();
-        if re.is_match(str.as_ref()) {
-            Some(str)
-        } else {
-            None
+impl Passport<Unvalidated> {
+    pub fn custom_deserialize<T: AsRef<str>>(str: T) -> Result<Self, Vec<Violation>> {
+        let (record, malformed) = parse_record(str);
+        if !malformed.is_empty() {
+            return Err(malformed);
         }
+        Ok(Passport::from_record(record))
     }
 
-    pub fn parse_ecl<T: AsRef<str>>(str: T) -> Option<T> {
-        let re = Regex::new(r#"^(amb|blu|brn|gry|grn|hzl|oth)$"#).unwrap();
-        if re.is_match(str.as_ref()) {
-            Some(str)
-        } else {
-            None
+    fn from_record(mut record: Record) -> Self {
+        Passport {
+            byr: record.remove("byr"),
+            iyr: record.remove("iyr"),
+            eyr: record.remove("eyr"),
+            hgt: record.remove("hgt"),
+            hcl: record.remove("hcl"),
+            ecl: record.remove("ecl"),
+            pid: record.remove("pid"),
+            cid: record.remove("cid"),
         }
     }
 
-    pub fn parse_pid<T: AsRef<str>>(str: T) -> Option<T> {
-        let re = Regex::new(r#"^[0-9]{9}$"#).unwrap();
-        if re.is_match(str.as_ref()) {
-            Some(str)
+    // Part 1
+    pub fn check_fields(self) -> Result<Passport<FieldsPresent>, Vec<Violation>> {
+        let mut violations = vec![];
+        let mut require = |key: &str, value: Option<String>| {
+            value.unwrap_or_else(|| {
+                violations.push(Violation::new(key, None, Problem::Missing));
+                String::new()
+            })
+        };
+        let passport = Passport {
+            byr: require("byr", self.byr),
+            iyr: require("iyr", self.iyr),
+            eyr: require("eyr", self.eyr),
+            hgt: require("hgt", self.hgt),
+            hcl: require("hcl", self.hcl),
+            ecl: require("ecl", self.ecl),
+            pid: require("pid", self.pid),
+            cid: self.cid,
+        };
+
+        if violations.is_empty() {
+            Ok(passport)
         } else {
-            None
+            Err(violations)
+        }
+    }
+}
+
+impl Passport<FieldsPresent> {
+    // Part 2
+    pub fn validate(self, schema: &Schema) -> Result<Passport<Validated>, Vec<Violation>> {
+        schema.validate(&self.to_record())?;
+        Ok(Passport {
+            byr: self.byr,
+            iyr: self.iyr,
+            eyr: self.eyr,
+            hgt: self.hgt,
+            hcl: self.hcl,
+            ecl: self.ecl,
+            pid: self.pid,
+            cid: self.cid,
+        })
+    }
+
+    fn to_record(&self) -> Record {
+        let fields = [
+            ("byr", &self.byr),
+            ("iyr", &self.iyr),
+            ("eyr", &self.eyr),
+            ("hgt", &self.hgt),
+            ("hcl", &self.hcl),
+            ("ecl", &self.ecl),
+            ("pid", &self.pid),
+        ];
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .chain(self.cid.iter().map(|v| ("cid".to_string(), v.clone())))
+            .collect()
+    }
+}
+
+impl Passport<Validated> {
+    // Years as integers, heights in cm and colors as RGB
+    pub fn normalize(self) -> Passport<Normalized> {
+        Passport {
+            byr: Normal::new(self.byr, parse_year),
+            iyr: Normal::new(self.iyr, parse_year),
+            eyr: Normal::new(self.eyr, parse_year),
+            hgt: Normal::new(self.hgt, parse_height),
+            hcl: Normal::new(self.hcl, parse_color),
+            ecl: self.ecl,
+            pid: self.pid,
+            cid: self.cid,
         }
     }
 }
 
-fn bound_numeric_str<T: AsRef<str>>(str: T, lower: u32, upper: u32) -> Option<T> {
-    let n = str.as_ref().parse::<u32>().ok()?;
-    if n >= lower && n <= upper {
-        Some(str)
-    } else {
-        None
+pub fn from_multiline_input<T, S, F>(
+    arr: &[T],
+    stage: F,
+) -> Vec<Result<Passport<S>, Vec<Diagnostic>>>
+where
+    T: AsRef<str> + Borrow<str>,
+    S: Stage,
+    F: Fn(String) -> Result<Passport<S>, Vec<Violation>>,
+{
+    records(arr)
+        .into_iter()
+        .map(|(line, record)| {
+            stage(record).map_err(|violations| {
+                violations
+                    .into_iter()
+                    .map(|violation| Diagnostic { line, violation })
+                    .collect()
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// Blank-line separated records, each paired with its 1-based starting line
+fn records<T: AsRef<str>>(arr: &[T]) -> Vec<(usize, String)> {
+    let mut result: Vec<(usize, String)> = vec![];
+    let mut current: Option<(usize, Vec<&str>)> = None;
+    for (i, line) in arr.iter().enumerate() {
+        let line = line.as_ref();
+        match (&mut current, line.is_empty()) {
+            (Some(_), true) => {
+                let (start, lines) = current.take().unwrap();
+                result.push((start, lines.join("\n")));
+            }
+            (None, true) => {}
+            (Some((_, lines)), false) => lines.push(line),
+            (None, false) => current = Some((i + 1, vec![line])),
+        }
+    }
+    if let Some((start, lines)) = current {
+        result.push((start, lines.join("\n")));
     }
+    result
 }
 
-pub fn count_valid<T: AsRef<str> + Borrow<str>>(input: &Vec<T>) -> usize {
-    Passport::from_multiline_input_p1(input)
+pub fn count_present<T: AsRef<str> + Borrow<str>>(input: &[T]) -> usize {
+    from_multiline_input(input, |r| Passport::custom_deserialize(r)?.check_fields())
         .into_iter()
-        .filter(|v| v.is_some())
+        .filter(|v| v.is_ok())
         .count()
 }
 
+pub fn count_valid<T: AsRef<str> + Borrow<str>>(input: &[T], schema: &Schema) -> usize {
+    from_multiline_input(input, |r| {
+        Passport::custom_deserialize(r)?
+            .check_fields()?
+            .validate(schema)
+    })
+    .into_iter()
+    .filter(|v| v.is_ok())
+    .count()
+}
+
+// Every problem in a record, unlike the stages above which stop at the first
+// one that fails
+fn problems<T: AsRef<str>>(record: T, schema: &Schema) -> Vec<Violation> {
+    let (record, mut violations) = parse_record(record);
+    if let Err(mut errors) = schema.validate(&record) {
+        violations.append(&mut errors);
+    }
+    violations
+}
+
+pub fn diagnose<T: AsRef<str>>(input: &[T], schema: &Schema) -> Vec<Diagnostic> {
+    records(input)
+        .into_iter()
+        .flat_map(|(line, record)| {
+            problems(record, schema)
+                .into_iter()
+                .map(move |violation| Diagnostic { line, violation })
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug)]
+pub struct Rejected {
+    line: usize,
+    #[serde(flatten)]
+    passport: Passport<Unvalidated>,
+    errors: String,
+}
+
+// Splits the input into normalized valid passports and rejected records
+pub fn sort_passports<T: AsRef<str>>(
+    input: &[T],
+    schema: &Schema,
+) -> (Vec<Passport<Normalized>>, Vec<Rejected>) {
+    let mut valid = vec![];
+    let mut rejected = vec![];
+    for (line, record) in records(input) {
+        let result = Passport::custom_deserialize(&record)
+            .and_then(Passport::check_fields)
+            .and_then(|p| p.validate(schema));
+        match result {
+            Ok(passport) => valid.push(passport.normalize()),
+            Err(mut violations) => {
+                let all = problems(&record, schema);
+                if !all.is_empty() {
+                    violations = all;
+                }
+                rejected.push(Rejected {
+                    line,
+                    passport: Passport::from_record(parse_record(&record).0),
+                    errors: violations
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                })
+            }
+        }
+    }
+    (valid, rejected)
+}
+
+// Failure causes across all records, most common first
+pub fn summarize(diagnostics: &[Diagnostic]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for d in diagnostics {
+        *counts.entry(d.violation.cause()).or_insert(0) += 1;
+    }
+    let mut summary = counts.into_iter().collect::<Vec<_>>();
+    summary.sort_by(|(a, n), (b, m)| m.cmp(n).then(a.cmp(b)));
+    summary
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -170,25 +468,26 @@ mod test {
     ];
 
     #[test]
-    #[ignore]
     fn success_deserialize_without_newline() {
         let input = "ecl:gry pid:860033327 eyr:2020 hcl:#fffff byr:1937 iyr:2017 cid:147 hgt:183cm";
-        assert!(Passport::custom_deserialize(&input).is_some());
+        assert!(Passport::custom_deserialize(input)
+            .and_then(Passport::check_fields)
+            .is_ok());
     }
 
     #[test]
-    #[ignore]
     fn success_deserialize_with_newline() {
         let input = [
             "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd",
             "byr:1937 iyr:2017 cid:147 hgt:183cm",
         ]
         .join("\n");
-        assert!(Passport::custom_deserialize(&input).is_some());
+        assert!(Passport::custom_deserialize(&input)
+            .and_then(Passport::check_fields)
+            .is_ok());
     }
 
     #[test]
-    #[ignore]
     fn success_deserialize_without_cid() {
         let input = [
             "hcl:#ae17e1 iyr:2013",
@@ -197,45 +496,110 @@ mod test {
             "hgt:179cm",
         ]
         .join("\n");
-        assert!(Passport::custom_deserialize(&input).is_some());
+        assert!(Passport::custom_deserialize(&input)
+            .and_then(Passport::check_fields)
+            .is_ok());
     }
 
     #[test]
-    #[ignore]
     fn failue_deserialize_without_mandatory_fields() {
         let input = [
             "iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884",
             "hcl:#cfa07d byr:1929",
         ]
         .join("\n");
-        assert!(Passport::custom_deserialize(&input).is_none());
+        assert!(Passport::custom_deserialize(&input)
+            .and_then(Passport::check_fields)
+            .is_err());
     }
 
     #[test]
-    #[ignore]
     fn failue_deserialize_without_other_mandatory_fields() {
         let input = [
             "hcl:#cfa07d eyr:2025 pid:166559648",
             "iyr:2011 ecl:brn hgt:59in",
         ]
         .join("\n");
-        assert!(Passport::custom_deserialize(&input).is_none());
+        assert!(Passport::custom_deserialize(&input)
+            .and_then(Passport::check_fields)
+            .is_err());
     }
 
     #[test]
-    #[ignore]
     fn example_p1() {
-        assert_eq!(count_valid(&INPUT.to_vec()), 2);
+        assert_eq!(count_present(&INPUT), 2);
     }
 
     #[test]
     fn valid_pid() {
-        assert!(Passport::parse_pid("000000001").is_some());
+        let schema = Schema::passport();
+        assert!(schema.field("pid").unwrap().rule.check("000000001").is_ok());
     }
 
     #[test]
     fn invalid_pid() {
-        assert!(Passport::parse_pid("0123456789").is_none());
+        let schema = Schema::passport();
+        assert_eq!(
+            schema.field("pid").unwrap().rule.check("0123456789"),
+            Err(Problem::NoMatch("^[0-9]{9}$".to_string()))
+        );
+    }
+
+    #[test]
+    fn hgt_rules() {
+        let schema = Schema::passport();
+        let rule = &schema.field("hgt").unwrap().rule;
+        assert!(rule.check("60in").is_ok());
+        assert!(rule.check("190cm").is_ok());
+        assert_eq!(rule.check("190in"), Err(Problem::OutOfRange(59, 76)));
+        assert_eq!(
+            rule.check("190"),
+            Err(Problem::MissingUnit(vec![
+                "cm".to_string(),
+                "in".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn json_schema_matches_builtin() {
+        let schema = passport_schema(include_str!("../passport_schema.json")).unwrap();
+        let input = [
+            "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980",
+            "hcl:#623a2f",
+            "",
+            "eyr:1972 cid:100",
+            "hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926",
+            "",
+            "iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719",
+        ];
+        assert_eq!(count_valid(&input, &schema), 2);
+    }
+
+    #[test]
+    fn schema_rejects_duplicate_fields() {
+        let result = Schema::builder().optional("cid").optional("cid").build();
+        assert!(matches!(result, Err(SchemaError::DuplicateField(f)) if f == "cid"));
+    }
+
+    #[test]
+    fn schema_must_require_passport_fields() {
+        let result = passport_schema(
+            r#"{ "fields": [
+                { "name": "byr", "type": "any" },
+                { "name": "iyr", "type": "any" },
+                { "name": "eyr", "type": "any" },
+                { "name": "hgt", "type": "any", "optional": true },
+                { "name": "hcl", "type": "any" },
+                { "name": "ecl", "type": "any" },
+                { "name": "pid", "type": "any" }
+            ] }"#,
+        );
+        assert!(matches!(result, Err(SchemaError::NotRequired(f)) if f == "hgt"));
+
+        let result = passport_schema(r#"{ "fields": [{ "name": "byr", "type": "any" }] }"#);
+        assert!(matches!(result, Err(SchemaError::NotRequired(f)) if f == "iyr"));
+        assert!(Schema::passport().require(&REQUIRED).is_ok());
     }
 
     #[test]
@@ -254,7 +618,7 @@ mod test {
             "",
             "iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719",
         ];
-        assert_eq!(count_valid(&input.to_vec()), 4);
+        assert_eq!(count_valid(&input, &Schema::passport()), 4);
     }
     #[test]
     fn all_invalids_p2() {
@@ -273,6 +637,178 @@ mod test {
             "eyr:2038 hcl:74454a iyr:2023",
             "pid:3556412378 byr:2007",
         ];
-        assert_eq!(count_valid(&input.to_vec()), 0);
+        assert_eq!(count_valid(&input, &Schema::passport()), 0);
+    }
+
+    #[test]
+    fn diagnostics_report_every_problem() {
+        let input = [
+            "iyr:2019",
+            "hcl:#602927 eyr:1967 hgt:170cm",
+            "ecl:grn pid:012533040 byr:1946",
+            "",
+            "eyr:1972 cid:100",
+            "hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926",
+        ];
+        let diagnostics = diagnose(&input, &Schema::passport());
+        let first = diagnostics
+            .iter()
+            .filter(|d| d.line == 1)
+            .collect::<Vec<_>>();
+        assert_eq!(first.len(), 1);
+        assert_eq!(
+            first[0].to_string(),
+            "line 1: eyr \"1967\": out of range 2020..=2030"
+        );
+
+        assert_eq!(
+            diagnostics[1..]
+                .iter()
+                .map(|d| d.violation.cause())
+                .collect::<Vec<_>>(),
+            vec![
+                "eyr out of range 2020..=2030",
+                "hgt missing unit (cm/in)",
+                "pid does not match ^[0-9]{9}$",
+            ]
+        );
+    }
+
+    #[test]
+    fn diagnostics_name_missing_fields() {
+        let input = "iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884\nhcl:#cfa07d byr:1929 junk";
+        let diagnostics = diagnose(&[input], &Schema::passport());
+        assert_eq!(
+            diagnostics
+                .into_iter()
+                .map(|d| d.violation)
+                .collect::<Vec<_>>(),
+            vec![
+                Violation::new("junk", None, Problem::Malformed),
+                Violation::new("hgt", None, Problem::Missing),
+            ]
+        );
+    }
+
+    #[test]
+    fn summary_orders_by_frequency() {
+        let input = ["byr:1900", "", "byr:2020 hgt:20in", "", "iyr:2015"];
+        let diagnostics = diagnose(&input, &Schema::passport());
+        let summary = summarize(&diagnostics);
+        assert_eq!(summary[0], ("ecl missing".to_string(), 3));
+        assert!(summary.contains(&("byr out of range 1920..=2002".to_string(), 2)));
+        assert!(summary.contains(&("hgt out of range 59..=76".to_string(), 1)));
+    }
+
+    #[test]
+    fn stages_are_checked_in_order() {
+        let input = "eyr:1972 cid:100 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926";
+        let present = Passport::custom_deserialize(input)
+            .and_then(Passport::check_fields)
+            .unwrap();
+        assert_eq!(present.hgt, "170");
+
+        let violations = present.validate(&Schema::passport()).unwrap_err();
+        assert_eq!(violations.len(), 3);
+    }
+
+    #[test]
+    fn check_fields_lists_missing_fields() {
+        let violations = Passport::custom_deserialize("byr:1937 iyr:2017 hgt:183cm")
+            .and_then(Passport::check_fields)
+            .unwrap_err();
+        let missing = violations
+            .iter()
+            .map(|v| v.field.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(missing, vec!["eyr", "hcl", "ecl", "pid"]);
+    }
+
+    #[test]
+    fn validated_passports_are_normalized() {
+        let input = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f";
+        let passport = Passport::custom_deserialize(input)
+            .and_then(Passport::check_fields)
+            .and_then(|p| p.validate(&Schema::passport()))
+            .unwrap();
+        assert_eq!(passport.hgt, "74in");
+
+        let passport = passport.normalize();
+        assert_eq!(passport.byr, Normal::Parsed(1980));
+        assert!(
+            matches!(passport.hgt, Normal::Parsed(Height { cm }) if (cm - 187.96).abs() < 1e-9)
+        );
+        assert_eq!(
+            passport.hcl,
+            Normal::Parsed(Rgb {
+                r: 0x62,
+                g: 0x3a,
+                b: 0x2f
+            })
+        );
+    }
+
+    #[test]
+    fn looser_schema_values_are_exported_as_written() {
+        let schema = passport_schema(
+            r#"{ "fields": [
+                { "name": "byr", "type": "any" },
+                { "name": "iyr", "type": "any" },
+                { "name": "eyr", "type": "any" },
+                { "name": "hgt", "type": "unit_int", "units": { "mm": [1500, 1930] } },
+                { "name": "hcl", "type": "enum", "values": ["red"] },
+                { "name": "ecl", "type": "any" },
+                { "name": "pid", "type": "any" }
+            ] }"#,
+        )
+        .unwrap();
+        let input = ["byr:1980 iyr:2012 eyr:2030 hgt:1800mm hcl:red ecl:grn pid:1"];
+        assert_eq!(count_valid(&input, &schema), 1);
+
+        let (valid, rejected) = sort_passports(&input, &schema);
+        assert!(rejected.is_empty());
+        assert_eq!(valid[0].hgt, Normal::Raw("1800mm".to_string()));
+        assert_eq!(valid[0].hcl, Normal::Raw("red".to_string()));
+    }
+
+    #[test]
+    fn export_valid_and_rejected() {
+        let input = [
+            "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980",
+            "hcl:#623a2f",
+            "",
+            "hgt:59cm ecl:zzz",
+        ];
+        let (valid, rejected) = sort_passports(&input, &Schema::passport());
+
+        let mut out = vec![];
+        Format::JsonLines.write(&mut out, &valid).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"{"byr":1980,"iyr":2012,"eyr":2030,"hgt":{"cm":187.96},"#,
+                r#""hcl":{"r":98,"g":58,"b":47},"ecl":"grn","pid":"087499704","cid":null}"#,
+                "\n"
+            )
+        );
+
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].line, 4);
+        let mut out = vec![];
+        Format::Csv.write(&mut out, &rejected).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "line,byr,iyr,eyr,hgt,hcl,ecl,pid,cid,errors");
+        assert!(lines[1].starts_with("4,,,,59cm,,zzz,,,\"byr missing; iyr missing;"));
+
+        let mut out = vec![];
+        Format::Csv.write(&mut out, &valid).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "byr,iyr,eyr,hgt.cm,hcl.r,hcl.g,hcl.b,ecl,pid,cid\n",
+                "1980,2012,2030,187.96,98,58,47,grn,087499704,\n"
+            )
+        );
     }
 }
@@ -0,0 +1,298 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+pub type Record = HashMap<String, String>;
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Json(serde_json::Error),
+    Regex(regex::Error),
+    DuplicateField(String),
+    // A field the caller needs on every record is missing or optional
+    NotRequired(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Problem {
+    Missing,
+    Malformed,
+    NotANumber,
+    OutOfRange(i64, i64),
+    MissingUnit(Vec<String>),
+    UnknownUnit(String),
+    NoMatch(String),
+    NotOneOf(Vec<String>),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::Malformed => write!(f, "not a key:value pair"),
+            Problem::NotANumber => write!(f, "not a number"),
+            Problem::OutOfRange(min, max) => write!(f, "out of range {}..={}", min, max),
+            Problem::MissingUnit(units) => write!(f, "missing unit ({})", units.join("/")),
+            Problem::UnknownUnit(unit) => write!(f, "unknown unit {:?}", unit),
+            Problem::NoMatch(pattern) => write!(f, "does not match {}", pattern),
+            Problem::NotOneOf(values) => write!(f, "not one of {}", values.join("/")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Violation {
+    pub field: String,
+    pub value: Option<String>,
+    pub problem: Problem,
+}
+
+impl Violation {
+    pub fn new(field: &str, value: Option<&str>, problem: Problem) -> Self {
+        Violation {
+            field: field.to_string(),
+            value: value.map(|v| v.to_string()),
+            problem,
+        }
+    }
+
+    // Same as `Display` minus the offending value, so equal causes group together
+    pub fn cause(&self) -> String {
+        format!("{} {}", self.field, self.problem)
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{} {:?}: {}", self.field, value, self.problem),
+            None => write!(f, "{} {}", self.field, self.problem),
+        }
+    }
+}
+
+// Serialized form of a rule, shared by the JSON loader and the builder
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RuleDef {
+    Any,
+    BoundedInt { min: i64, max: i64 },
+    UnitInt { units: HashMap<String, (i64, i64)> },
+    Regex { pattern: String },
+    Enum { values: Vec<String> },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct FieldDef {
+    name: String,
+    #[serde(flatten)]
+    rule: RuleDef,
+    #[serde(default)]
+    optional: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct SchemaDef {
+    fields: Vec<FieldDef>,
+}
+
+#[derive(Debug)]
+pub enum Rule {
+    Any,
+    BoundedInt { min: i64, max: i64 },
+    UnitInt(Vec<(String, i64, i64)>),
+    Regex(Regex),
+    Enum(Vec<String>),
+}
+
+impl Rule {
+    fn compile(def: RuleDef) -> Result<Rule, SchemaError> {
+        Ok(match def {
+            RuleDef::Any => Rule::Any,
+            RuleDef::BoundedInt { min, max } => Rule::BoundedInt { min, max },
+            RuleDef::UnitInt { units } => {
+                let mut units = units
+                    .into_iter()
+                    .map(|(unit, (min, max))| (unit, min, max))
+                    .collect::<Vec<_>>();
+                units.sort();
+                Rule::UnitInt(units)
+            }
+            RuleDef::Regex { pattern } => {
+                Rule::Regex(Regex::new(&pattern).map_err(SchemaError::Regex)?)
+            }
+            RuleDef::Enum { values } => Rule::Enum(values),
+        })
+    }
+
+    pub fn check(&self, value: &str) -> Result<(), Problem> {
+        let bounded = |n: i64, min: i64, max: i64| {
+            if n >= min && n <= max {
+                Ok(())
+            } else {
+                Err(Problem::OutOfRange(min, max))
+            }
+        };
+        match self {
+            Rule::Any => Ok(()),
+            Rule::BoundedInt { min, max } => {
+                let n = value.parse::<i64>().map_err(|_| Problem::NotANumber)?;
+                bounded(n, *min, *max)
+            }
+            Rule::UnitInt(units) => {
+                let split = value
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(value.len());
+                let (n, unit) = value.split_at(split);
+                let n = n.parse::<i64>().map_err(|_| Problem::NotANumber)?;
+                if unit.is_empty() {
+                    let names = units.iter().map(|(u, _, _)| u.clone()).collect();
+                    return Err(Problem::MissingUnit(names));
+                }
+                match units.iter().find(|(u, _, _)| u == unit) {
+                    Some((_, min, max)) => bounded(n, *min, *max),
+                    None => Err(Problem::UnknownUnit(unit.to_string())),
+                }
+            }
+            Rule::Regex(re) if re.is_match(value) => Ok(()),
+            Rule::Regex(re) => Err(Problem::NoMatch(re.as_str().to_string())),
+            Rule::Enum(values) if values.iter().any(|v| v == value) => Ok(()),
+            Rule::Enum(values) => Err(Problem::NotOneOf(values.clone())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Field {
+    pub name: String,
+    pub rule: Rule,
+    pub optional: bool,
+}
+
+#[derive(Debug)]
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+impl Schema {
+    pub fn builder() -> SchemaBuilder {
+        SchemaBuilder { fields: vec![] }
+    }
+
+    pub fn from_json<T: AsRef<str>>(str: T) -> Result<Schema, SchemaError> {
+        let def: SchemaDef = serde_json::from_str(str.as_ref()).map_err(SchemaError::Json)?;
+        Schema::compile(def.fields)
+    }
+
+    // Rules from the puzzle's part 2
+    pub fn passport() -> Schema {
+        Schema::builder()
+            .bounded_int("byr", 1920, 2002)
+            .bounded_int("iyr", 2010, 2020)
+            .bounded_int("eyr", 2020, 2030)
+            .unit_int("hgt", &[("cm", 150, 193), ("in", 59, 76)])
+            .regex("hcl", r"^#[a-fA-F0-9]{6}$")
+            .one_of("ecl", &["amb", "blu", "brn", "gry", "grn", "hzl", "oth"])
+            .regex("pid", r"^[0-9]{9}$")
+            .optional("cid")
+            .build()
+            .expect("Invalid built-in passport schema")
+    }
+
+    fn compile(defs: Vec<FieldDef>) -> Result<Schema, SchemaError> {
+        let mut fields: Vec<Field> = Vec::with_capacity(defs.len());
+        for def in defs {
+            if fields.iter().any(|f| f.name == def.name) {
+                return Err(SchemaError::DuplicateField(def.name));
+            }
+            fields.push(Field {
+                name: def.name,
+                rule: Rule::compile(def.rule)?,
+                optional: def.optional,
+            });
+        }
+        Ok(Schema { fields })
+    }
+
+    // Refuses schemas that let any of `names` be left out
+    pub fn require(self, names: &[&str]) -> Result<Schema, SchemaError> {
+        for name in names {
+            if self.field(name).is_none_or(|f| f.optional) {
+                return Err(SchemaError::NotRequired(name.to_string()));
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    pub fn validate(&self, record: &Record) -> Result<(), Vec<Violation>> {
+        let violations = self
+            .fields
+            .iter()
+            .filter_map(|field| match record.get(&field.name) {
+                None if field.optional => None,
+                None => Some(Violation::new(&field.name, None, Problem::Missing)),
+                Some(value) => field
+                    .rule
+                    .check(value)
+                    .err()
+                    .map(|problem| Violation::new(&field.name, Some(value), problem)),
+            })
+            .collect::<Vec<_>>();
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+pub struct SchemaBuilder {
+    fields: Vec<FieldDef>,
+}
+
+impl SchemaBuilder {
+    fn rule(mut self, name: &str, rule: RuleDef, optional: bool) -> Self {
+        self.fields.push(FieldDef {
+            name: name.to_string(),
+            rule,
+            optional,
+        });
+        self
+    }
+
+    pub fn bounded_int(self, name: &str, min: i64, max: i64) -> Self {
+        self.rule(name, RuleDef::BoundedInt { min, max }, false)
+    }
+
+    pub fn unit_int(self, name: &str, units: &[(&str, i64, i64)]) -> Self {
+        let units = units
+            .iter()
+            .map(|(unit, min, max)| (unit.to_string(), (*min, *max)))
+            .collect();
+        self.rule(name, RuleDef::UnitInt { units }, false)
+    }
+
+    pub fn regex(self, name: &str, pattern: &str) -> Self {
+        let pattern = pattern.to_string();
+        self.rule(name, RuleDef::Regex { pattern }, false)
+    }
+
+    pub fn one_of(self, name: &str, values: &[&str]) -> Self {
+        let values = values.iter().map(|v| v.to_string()).collect();
+        self.rule(name, RuleDef::Enum { values }, false)
+    }
+
+    pub fn optional(self, name: &str) -> Self {
+        self.rule(name, RuleDef::Any, true)
+    }
+
+    pub fn build(self) -> Result<Schema, SchemaError> {
+        Schema::compile(self.fields)
+    }
+}
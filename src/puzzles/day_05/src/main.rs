@@ -1,60 +1,488 @@
-use std::isize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 use utils::read_lines;
 
 fn main() {
     let lines = read_lines("src/inputs/day_05.txt")
         .unwrap()
-        .flatten()
+        .map_while(Result::ok)
         .collect::<Vec<_>>();
 
-    let ids = lines.into_iter().map(|line| {
-        let BoardingPass { col, row } = BoardingPass::from_str(line);
-        row * 8 + col
-    });
+    let passes = lines
+        .iter()
+        .map(|line| line.parse::<BoardingPass>())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let seat_map = SeatMap::new(Geometry::default(), &passes).unwrap();
 
-    let max_id = ids.clone().max().unwrap();
+    // `day_05 map` prints the plane and an occupancy report
+    if std::env::args().nth(1).as_deref() == Some("map") {
+        print!("{}", seat_map);
+        let (front, back) = seat_map.missing_rows();
+        println!("Missing front rows: {:?}", front);
+        println!("Missing back rows: {:?}", back);
+        println!("Duplicate passes: {:?}", seat_map.duplicates());
+        return;
+    }
+
+    let max_id = passes.iter().map(BoardingPass::seat_id).max().unwrap();
     println!("Part 1: {:?}", max_id);
 
-    let min_id = ids.clone().min().unwrap();
-    let ids = ids.collect::<Vec<_>>();
-    let maybe_found = (min_id..=max_id)
-        .find(|id| !ids.contains(id) && ids.contains(&(*id + 1)) && ids.contains(&(*id - 1)));
-    println!("Part 2: {:?}", maybe_found);
+    println!("Part 2: {:?}", seat_map.gaps());
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PassError {
+    InvalidLength { expected: usize, found: usize },
+    InvalidChar(usize, char),
+    SeatOutOfRange(isize),
+    RowOutOfRange(isize),
+    ColOutOfRange(isize),
+    // Index of a pass laid out for another plane
+    GeometryMismatch(usize),
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassError::InvalidLength { expected, found } => {
+                write!(f, "expected {} characters, got {}", expected, found)
+            }
+            PassError::InvalidChar(i, c) => write!(f, "unexpected {:?} at position {}", c, i),
+            PassError::SeatOutOfRange(id) => write!(f, "seat {} is not on the plane", id),
+            PassError::RowOutOfRange(row) => write!(f, "row {} is not on the plane", row),
+            PassError::ColOutOfRange(col) => write!(f, "column {} is not on the plane", col),
+            PassError::GeometryMismatch(i) => write!(f, "pass {} is for another plane", i),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GeometryError {
+    // Seat IDs have to fit in an `isize`
+    TooManyBits(usize),
+    SameLetters(char),
+}
+
+impl fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeometryError::TooManyBits(bits) => {
+                write!(
+                    f,
+                    "{} bits is more than the {} seat IDs fit in",
+                    bits, MAX_BITS
+                )
+            }
+            GeometryError::SameLetters(c) => write!(f, "{:?} can't mark both halves", c),
+        }
+    }
+}
+
+const MAX_BITS: usize = 62;
+
+// Plane layout: how many bits pick the row and the column, and which letter
+// pair stands for the lower and upper half of each
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    row_bits: usize,
+    col_bits: usize,
+    row_letters: (char, char),
+    col_letters: (char, char),
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Geometry::new(7, 3).unwrap()
+    }
+}
+
+impl Geometry {
+    pub fn new(row_bits: usize, col_bits: usize) -> Result<Self, GeometryError> {
+        let bits = row_bits + col_bits;
+        if bits > MAX_BITS {
+            return Err(GeometryError::TooManyBits(bits));
+        }
+        Ok(Geometry {
+            row_bits,
+            col_bits,
+            row_letters: ('F', 'B'),
+            col_letters: ('L', 'R'),
+        })
+    }
+
+    pub fn with_letters(
+        self,
+        row_letters: (char, char),
+        col_letters: (char, char),
+    ) -> Result<Self, GeometryError> {
+        for (zero, one) in [row_letters, col_letters] {
+            if zero == one {
+                return Err(GeometryError::SameLetters(zero));
+            }
+        }
+        Ok(Geometry {
+            row_letters,
+            col_letters,
+            ..self
+        })
+    }
+
+    pub fn rows(&self) -> isize {
+        1 << self.row_bits
+    }
+
+    pub fn cols(&self) -> isize {
+        1 << self.col_bits
+    }
+
+    pub fn parse(&self, s: &str) -> Result<BoardingPass, PassError> {
+        let expected = self.row_bits + self.col_bits;
+        let found = s.chars().count();
+        if found != expected {
+            return Err(PassError::InvalidLength { expected, found });
+        }
+        let split = s
+            .char_indices()
+            .nth(self.row_bits)
+            .map_or(s.len(), |(i, _)| i);
+        let (row, col) = s.split_at(split);
+
+        Ok(BoardingPass {
+            row: decode(row, 0, self.row_letters)?,
+            col: decode(col, self.row_bits, self.col_letters)?,
+            geometry: *self,
+        })
+    }
+
+    pub fn seat(&self, id: isize) -> Result<BoardingPass, PassError> {
+        if !(0..self.rows() * self.cols()).contains(&id) {
+            return Err(PassError::SeatOutOfRange(id));
+        }
+        Ok(BoardingPass {
+            row: id / self.cols(),
+            col: id % self.cols(),
+            geometry: *self,
+        })
+    }
+
+    pub fn at(&self, row: isize, col: isize) -> Result<BoardingPass, PassError> {
+        if !(0..self.rows()).contains(&row) {
+            return Err(PassError::RowOutOfRange(row));
+        }
+        if !(0..self.cols()).contains(&col) {
+            return Err(PassError::ColOutOfRange(col));
+        }
+        Ok(BoardingPass {
+            row,
+            col,
+            geometry: *self,
+        })
+    }
 }
 
 #[derive(PartialEq, Eq, Debug)]
 pub struct BoardingPass {
     col: isize,
     row: isize,
+    geometry: Geometry,
 }
 
 impl BoardingPass {
-    pub fn from_str<T: AsRef<str>>(s: T) -> BoardingPass {
-        let (row, col) = s.as_ref().split_at(7);
-        let transform = |str: &str, one: char| -> String {
-            str.chars()
-                .map(|c| if c == one { '1' } else { '0' })
-                .collect::<String>()
-        };
+    pub fn new(row: isize, col: isize) -> Result<BoardingPass, PassError> {
+        Geometry::default().at(row, col)
+    }
+
+    pub fn from_seat_id(id: isize) -> Result<BoardingPass, PassError> {
+        Geometry::default().seat(id)
+    }
+
+    pub fn seat_id(&self) -> isize {
+        self.row * self.geometry.cols() + self.col
+    }
+}
+
+// Each half of the pass is a binary number where the second letter marks a set bit
+fn decode(s: &str, offset: usize, (zero, one): (char, char)) -> Result<isize, PassError> {
+    s.chars().enumerate().try_fold(0, |acc, (i, c)| match c {
+        _ if c == zero => Ok(acc << 1),
+        _ if c == one => Ok(acc << 1 | 1),
+        _ => Err(PassError::InvalidChar(offset + i, c)),
+    })
+}
+
+fn encode(n: isize, bits: usize, (zero, one): (char, char)) -> impl Iterator<Item = char> {
+    (0..bits)
+        .rev()
+        .map(move |bit| if n >> bit & 1 == 1 { one } else { zero })
+}
 
-        let row = transform(row, 'B');
-        let col = transform(col, 'R');
+impl FromStr for BoardingPass {
+    type Err = PassError;
 
-        BoardingPass {
-            col: isize::from_str_radix(&col[..], 2).unwrap(),
-            row: isize::from_str_radix(&row[..], 2).unwrap(),
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Geometry::default().parse(s)
+    }
+}
+
+impl fmt::Display for BoardingPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Geometry {
+            row_bits,
+            col_bits,
+            row_letters,
+            col_letters,
+        } = self.geometry;
+        let pass = encode(self.row, row_bits, row_letters)
+            .chain(encode(self.col, col_bits, col_letters))
+            .collect::<String>();
+        write!(f, "{}", pass)
+    }
+}
+
+// How many passes were issued for each occupied seat, by seat ID. Kept sparse
+// since a geometry may describe far more seats than there are passes.
+pub struct SeatMap {
+    geometry: Geometry,
+    counts: BTreeMap<isize, usize>,
+}
+
+impl SeatMap {
+    pub fn new(geometry: Geometry, passes: &[BoardingPass]) -> Result<Self, PassError> {
+        let seats = geometry.rows() * geometry.cols();
+        let mut counts = BTreeMap::new();
+        for (i, pass) in passes.iter().enumerate() {
+            if pass.geometry != geometry {
+                return Err(PassError::GeometryMismatch(i));
+            }
+            let id = pass.seat_id();
+            if !(0..seats).contains(&id) {
+                return Err(PassError::SeatOutOfRange(id));
+            }
+            *counts.entry(id).or_insert(0) += 1;
+        }
+        Ok(SeatMap { geometry, counts })
+    }
+
+    fn is_occupied(&self, id: isize) -> bool {
+        self.counts.contains_key(&id)
+    }
+
+    // Empty seats whose neighbouring seat IDs are both taken
+    pub fn gaps(&self) -> Vec<isize> {
+        self.counts
+            .keys()
+            .map(|id| id + 1)
+            .filter(|id| !self.is_occupied(*id) && self.is_occupied(id + 1))
+            .collect()
+    }
+
+    pub fn duplicates(&self) -> Vec<(BoardingPass, usize)> {
+        self.counts
+            .iter()
+            .filter(|(_, count)| **count > 1)
+            .map(|(id, count)| (self.geometry.seat(*id).unwrap(), *count))
+            .collect()
+    }
+
+    // Empty rows before the first and after the last occupied one
+    pub fn missing_rows(&self) -> (Range<isize>, Range<isize>) {
+        let cols = self.geometry.cols();
+        let rows = self.geometry.rows();
+        match (self.counts.keys().next(), self.counts.keys().next_back()) {
+            (Some(first), Some(last)) => (0..first / cols, last / cols + 1..rows),
+            _ => (0..rows, rows..rows),
         }
     }
 }
 
+// One line per row: `.` empty, `#` taken, `!` issued more than once
+impl fmt::Display for SeatMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cols = self.geometry.cols();
+        for row in 0..self.geometry.rows() {
+            let seats = (0..cols)
+                .map(|col| match self.counts.get(&(row * cols + col)) {
+                    None => '.',
+                    Some(1) => '#',
+                    Some(_) => '!',
+                })
+                .collect::<String>();
+            writeln!(f, "{:>4} {}", row, seats)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn examples_p1() {
-        assert_eq!(BoardingPass::from_str("BFFFBBFRRR"), BoardingPass { row: 70, col: 7 });
-        assert_eq!(BoardingPass::from_str("FFFBBBFRRR"), BoardingPass { row: 14, col: 7 });
-        assert_eq!(BoardingPass::from_str("BBFFBBFRLL"), BoardingPass { row: 102, col: 4 });
+        assert_eq!(
+            BoardingPass::from_str("BFFFBBFRRR"),
+            BoardingPass::new(70, 7)
+        );
+        assert_eq!(
+            BoardingPass::from_str("FFFBBBFRRR"),
+            BoardingPass::new(14, 7)
+        );
+        assert_eq!(
+            BoardingPass::from_str("BBFFBBFRLL"),
+            BoardingPass::new(102, 4)
+        );
+    }
+
+    #[test]
+    fn seat_ids() {
+        assert_eq!(BoardingPass::new(44, 5).unwrap().seat_id(), 357);
+        assert_eq!(BoardingPass::new(102, 4).unwrap().seat_id(), 820);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let pass = BoardingPass::from_seat_id(357).unwrap();
+        assert_eq!(BoardingPass::new(44, 5).as_ref(), Ok(&pass));
+        assert_eq!(pass.to_string(), "FBFBBFFRLR");
+        assert_eq!(
+            BoardingPass::from_str("BBFFBBFRLL").unwrap().to_string(),
+            "BBFFBBFRLL"
+        );
+    }
+
+    #[test]
+    fn invalid_passes() {
+        assert_eq!(
+            BoardingPass::from_str("FBFBBFFRL"),
+            Err(PassError::InvalidLength {
+                expected: 10,
+                found: 9
+            })
+        );
+        assert_eq!(
+            BoardingPass::from_str("FBFBXFFRLR"),
+            Err(PassError::InvalidChar(4, 'X'))
+        );
+        assert_eq!(
+            BoardingPass::from_str("FBFBBFFRLB"),
+            Err(PassError::InvalidChar(9, 'B'))
+        );
+        assert_eq!(
+            BoardingPass::from_seat_id(1024),
+            Err(PassError::SeatOutOfRange(1024))
+        );
+        assert_eq!(
+            BoardingPass::new(200, 0),
+            Err(PassError::RowOutOfRange(200))
+        );
+        assert_eq!(BoardingPass::new(0, -1), Err(PassError::ColOutOfRange(-1)));
+    }
+
+    #[test]
+    fn larger_planes() {
+        let geometry = Geometry::new(8, 4).unwrap();
+        let pass = geometry.parse("BBBBBBBBRRRR").unwrap();
+        assert_eq!((pass.row, pass.col), (255, 15));
+        assert_eq!(pass.seat_id(), 255 * 16 + 15);
+        assert_eq!(geometry.seat(4095).unwrap(), pass);
+        assert_eq!(geometry.seat(4096), Err(PassError::SeatOutOfRange(4096)));
+    }
+
+    #[test]
+    fn custom_letters() {
+        let geometry = Geometry::new(7, 3)
+            .unwrap()
+            .with_letters(('0', '1'), ('a', 'b'))
+            .unwrap();
+        let pass = geometry.parse("0101100bab").unwrap();
+        assert_eq!(pass.seat_id(), 357);
+        assert_eq!(pass.to_string(), "0101100bab");
+        assert_eq!(
+            geometry.parse("FBFBBFFRLR"),
+            Err(PassError::InvalidChar(0, 'F'))
+        );
+    }
+
+    #[test]
+    fn invalid_geometry() {
+        assert_eq!(Geometry::new(32, 31), Err(GeometryError::TooManyBits(63)));
+        assert!(Geometry::new(31, 31).is_ok());
+        assert_eq!(
+            Geometry::default().with_letters(('A', 'A'), ('L', 'R')),
+            Err(GeometryError::SameLetters('A'))
+        );
+        assert_eq!(
+            Geometry::default().with_letters(('F', 'B'), ('R', 'R')),
+            Err(GeometryError::SameLetters('R'))
+        );
+    }
+
+    #[test]
+    fn seat_map_report() {
+        let geometry = Geometry::new(3, 2).unwrap();
+        let passes = [0, 1, 2, 6, 8, 8, 10, 11]
+            .iter()
+            .map(|id| geometry.seat(*id).unwrap())
+            .collect::<Vec<_>>();
+        let seat_map = SeatMap::new(geometry, &passes).unwrap();
+
+        assert_eq!(seat_map.gaps(), vec![7, 9]);
+        assert_eq!(seat_map.duplicates(), vec![(geometry.seat(8).unwrap(), 2)]);
+        assert_eq!(seat_map.missing_rows(), (0..0, 3..8));
+        assert_eq!(
+            seat_map.to_string(),
+            [
+                "   0 ###.",
+                "   1 ..#.",
+                "   2 !.##",
+                "   3 ....",
+                "   4 ....",
+                "   5 ....",
+                "   6 ....",
+                "   7 ....",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn seat_map_rejects_other_planes() {
+        let passes = [
+            BoardingPass::new(44, 5).unwrap(),
+            Geometry::new(8, 4).unwrap().seat(4095).unwrap(),
+        ];
+        assert_eq!(
+            SeatMap::new(Geometry::default(), &passes).err(),
+            Some(PassError::GeometryMismatch(1))
+        );
+
+        // Same seat count, different split
+        let passes = [Geometry::new(6, 4).unwrap().seat(17).unwrap()];
+        assert_eq!(
+            SeatMap::new(Geometry::default(), &passes).err(),
+            Some(PassError::GeometryMismatch(0))
+        );
+    }
+
+    #[test]
+    fn seat_map_of_huge_planes() {
+        let geometry = Geometry::new(31, 31).unwrap();
+        let last = geometry.rows() * geometry.cols() - 1;
+        let passes = [0, 2, last - 2, last]
+            .iter()
+            .map(|id| geometry.seat(*id).unwrap())
+            .collect::<Vec<_>>();
+        let seat_map = SeatMap::new(geometry, &passes).unwrap();
+
+        assert_eq!(seat_map.gaps(), vec![1, last - 1]);
+        assert_eq!(
+            seat_map.missing_rows(),
+            (0..0, geometry.rows()..geometry.rows())
+        );
+        assert!(seat_map.duplicates().is_empty());
     }
 }
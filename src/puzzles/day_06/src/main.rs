@@ -1,50 +1,307 @@
-use std::{borrow::Borrow, collections::HashMap};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitXor};
+use std::str::FromStr;
 use utils::read_lines;
 
 fn main() {
     let lines = read_lines("src/inputs/day_06.txt")
         .unwrap()
-        .flatten()
+        .map_while(Result::ok)
         .collect::<Vec<_>>();
 
-    let result = solve_p1(&lines);
-    println!("Part 1: {:?}", result);
+    // `day_06 report [--json]` prints per-question statistics
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(|a| a.as_str()) == Some("report") {
+        let report = Report::new(&groups(&lines).unwrap());
+        if args.iter().any(|a| a == "--json") {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        } else {
+            print!("{}", report);
+        }
+        return;
+    }
+
+    match solve_p1(&lines) {
+        Ok(result) => println!("Part 1: {:?}", result),
+        Err(e) => println!("Part 1: {}", e),
+    }
+
+    match solve_p2(&lines) {
+        Ok(result) => println!("Part 2: {:?}", result),
+        Err(e) => println!("Part 2: {}", e),
+    }
+}
+
+// Questions `a` to `z` answered "yes", one bit per question
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Answers(u32);
+
+impl Answers {
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
 
-    let result = solve_p2(&lines);
-    println!("Part 2: {:?}", result);
+    pub fn contains(&self, question: char) -> bool {
+        question.is_ascii_lowercase() && self.0 & Answers::bit(question) != 0
+    }
+
+    pub fn questions(&self) -> impl Iterator<Item = char> + '_ {
+        ('a'..='z').filter(move |q| self.contains(*q))
+    }
+
+    fn bit(question: char) -> u32 {
+        1 << (question as u32 - 'a' as u32)
+    }
 }
 
-pub fn solve_p1<T: AsRef<str> + Borrow<str>>(lines: &Vec<T>) -> usize {
-    lines
-        .split(|s| s.as_ref() == "")
-        .map(|group| {
-            let mut chars = group.join("").chars().collect::<Vec<_>>();
-            chars.sort();
-            chars.dedup();
-            chars.len()
+impl FromStr for Answers {
+    type Err = char;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars().try_fold(Answers::default(), |acc, c| {
+            if c.is_ascii_lowercase() {
+                Ok(Answers(acc.0 | Answers::bit(c)))
+            } else {
+                Err(c)
+            }
         })
-        .fold(0, |acc, v| acc + v)
-}
-
-pub fn solve_p2<T: AsRef<str> + Borrow<str>>(lines: &Vec<T>) -> usize {
-    lines
-        .split(|s| s.as_ref() == "")
-        .map(|group| {
-            let people_qty = group.len();
-            group
-                .join("")
-                .chars()
-                .fold(HashMap::new(), |mut acc, ch| {
-                    let counter = acc.entry(ch).or_insert(0);
-                    *counter += 1;
-                    acc
-                })
-                .into_iter()
-                .filter(|(_, count)| *count == people_qty)
+    }
+}
+
+impl BitOr for Answers {
+    type Output = Answers;
+    fn bitor(self, rhs: Answers) -> Answers {
+        Answers(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Answers {
+    type Output = Answers;
+    fn bitand(self, rhs: Answers) -> Answers {
+        Answers(self.0 & rhs.0)
+    }
+}
+
+impl BitXor for Answers {
+    type Output = Answers;
+    fn bitxor(self, rhs: Answers) -> Answers {
+        Answers(self.0 ^ rhs.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    people: Vec<Answers>,
+}
+
+impl Group {
+    pub fn union(&self) -> Answers {
+        self.people
+            .iter()
+            .fold(Answers::default(), |acc, p| acc | *p)
+    }
+
+    pub fn intersection(&self) -> Answers {
+        let mut people = self.people.iter();
+        match people.next() {
+            Some(first) => people.fold(*first, |acc, p| acc & *p),
+            None => Answers::default(),
+        }
+    }
+
+    // Questions answered by an odd number of people
+    pub fn symmetric_difference(&self) -> Answers {
+        self.people
+            .iter()
+            .fold(Answers::default(), |acc, p| acc ^ *p)
+    }
+
+    pub fn at_least(&self, k: usize) -> Answers {
+        let mut answers = Answers::default();
+        for q in 0..26 {
+            let bit = 1 << q;
+            if self.people.iter().filter(|p| p.0 & bit != 0).count() >= k {
+                answers.0 |= bit;
+            }
+        }
+        answers
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AnswerError {
+    // 1-based
+    line: usize,
+    found: char,
+}
+
+impl fmt::Display for AnswerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {:?} is not a question", self.line, self.found)
+    }
+}
+
+pub fn groups<T: AsRef<str>>(lines: &[T]) -> Result<Vec<Group>, AnswerError> {
+    let mut groups = vec![];
+    let mut people = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.as_ref();
+        if line.is_empty() {
+            // Doubled or trailing blank lines don't make a group of nobody
+            if !people.is_empty() {
+                groups.push(Group {
+                    people: std::mem::take(&mut people),
+                });
+            }
+            continue;
+        }
+        let answers = line
+            .parse::<Answers>()
+            .map_err(|found| AnswerError { line: i + 1, found })?;
+        people.push(answers);
+    }
+    if !people.is_empty() {
+        groups.push(Group { people });
+    }
+    Ok(groups)
+}
+
+pub fn solve_p1<T: AsRef<str>>(lines: &[T]) -> Result<usize, AnswerError> {
+    Ok(groups(lines)?.iter().map(|group| group.union().len()).sum())
+}
+
+pub fn solve_p2<T: AsRef<str>>(lines: &[T]) -> Result<usize, AnswerError> {
+    Ok(groups(lines)?
+        .iter()
+        .map(|group| group.intersection().len())
+        .sum())
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct QuestionStats {
+    question: char,
+    people: usize,
+    groups: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Report {
+    groups: usize,
+    people: usize,
+    questions: Vec<QuestionStats>,
+    never_answered: Vec<char>,
+    // Group size -> number of groups of that size
+    group_sizes: BTreeMap<usize, usize>,
+    // 1-based numbers of groups with more than one person where everyone
+    // gave exactly the same answers
+    unanimous_groups: Vec<usize>,
+    // 1-based numbers of groups that answered the same set of questions as
+    // each other, one list per set shared by two groups or more
+    matching_groups: Vec<Vec<usize>>,
+}
+
+impl Report {
+    pub fn new(groups: &[Group]) -> Self {
+        let questions = ('a'..='z')
+            .map(|question| QuestionStats {
+                question,
+                people: groups
+                    .iter()
+                    .flat_map(|g| g.people.iter())
+                    .filter(|p| p.contains(question))
+                    .count(),
+                groups: groups
+                    .iter()
+                    .filter(|g| g.union().contains(question))
+                    .count(),
+            })
+            .collect::<Vec<_>>();
+        let never_answered = questions
+            .iter()
+            .filter(|q| q.people == 0)
+            .map(|q| q.question)
+            .collect();
+
+        let mut group_sizes = BTreeMap::new();
+        for group in groups {
+            *group_sizes.entry(group.people.len()).or_insert(0) += 1;
+        }
+
+        let unanimous_groups = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| g.people.len() > 1 && g.union() == g.intersection())
+            .map(|(i, _)| i + 1)
+            .collect();
+
+        let mut by_answers: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        for (i, group) in groups.iter().enumerate() {
+            by_answers.entry(group.union().0).or_default().push(i + 1);
+        }
+        let mut matching_groups = by_answers
+            .into_values()
+            .filter(|numbers| numbers.len() > 1)
+            .collect::<Vec<_>>();
+        matching_groups.sort();
+
+        Report {
+            groups: groups.len(),
+            people: groups.iter().map(|g| g.people.len()).sum(),
+            questions,
+            never_answered,
+            group_sizes,
+            unanimous_groups,
+            matching_groups,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} groups, {} people", self.groups, self.people)?;
+        writeln!(f)?;
+        writeln!(f, "question  people  groups")?;
+        for q in &self.questions {
+            writeln!(f, "{:>8}  {:>6}  {:>6}", q.question, q.people, q.groups)?;
+        }
+        writeln!(f)?;
+        let never = self.never_answered.iter().collect::<String>();
+        writeln!(
+            f,
+            "never answered: {}",
+            if never.is_empty() { "none" } else { &never }
+        )?;
+        writeln!(f)?;
+        writeln!(f, "group size  groups")?;
+        for (size, count) in &self.group_sizes {
+            writeln!(f, "{:>10}  {:>6}", size, count)?;
+        }
+        writeln!(f)?;
+        let list = |numbers: &[usize]| {
+            numbers
+                .iter()
+                .map(|g| g.to_string())
                 .collect::<Vec<_>>()
-                .len()
-        })
-        .fold(0, |acc, v| acc + v)
+                .join(", ")
+        };
+        writeln!(
+            f,
+            "everyone answered alike: {}",
+            list(&self.unanimous_groups)
+        )?;
+        let matching = self
+            .matching_groups
+            .iter()
+            .map(|numbers| list(numbers))
+            .collect::<Vec<_>>();
+        writeln!(f, "groups answered alike: {}", matching.join("; "))
+    }
 }
 
 #[cfg(test)]
@@ -57,7 +314,7 @@ mod test {
             "abc", "", "a", "b", "c", "", "ab", "ac", "", "a", "a", "a", "a", "", "b",
         ];
 
-        assert_eq!(solve_p1(&lines), 11);
+        assert_eq!(solve_p1(&lines), Ok(11));
     }
 
     #[test]
@@ -66,6 +323,74 @@ mod test {
             "abc", "", "a", "b", "c", "", "ab", "ac", "", "a", "a", "a", "a", "", "b",
         ];
 
-        assert_eq!(solve_p2(&lines), 6);
+        assert_eq!(solve_p2(&lines), Ok(6));
+    }
+
+    #[test]
+    fn group_algebra() {
+        let group = &groups(&["abc", "abd", "ae"]).unwrap()[0];
+        let set = |s: &str| s.parse::<Answers>().unwrap();
+
+        assert_eq!(group.union(), set("abcde"));
+        assert_eq!(group.intersection(), set("a"));
+        assert_eq!(group.symmetric_difference(), set("acde"));
+        assert_eq!(group.at_least(2), set("ab"));
+        assert_eq!(group.at_least(0), set("abcdefghijklmnopqrstuvwxyz"));
+        assert_eq!(group.union().questions().collect::<String>(), "abcde");
+    }
+
+    #[test]
+    fn invalid_answers() {
+        assert_eq!("abC".parse::<Answers>(), Err('C'));
+
+        let lines = ["abc", "", "ab\r", "a"];
+        let error = solve_p1(&lines).unwrap_err();
+        assert_eq!(
+            error,
+            AnswerError {
+                line: 3,
+                found: '\r'
+            }
+        );
+        assert_eq!(error.to_string(), "line 3: '\\r' is not a question");
+        assert!(solve_p2(&["a b"]).is_err());
+    }
+
+    #[test]
+    fn report_example() {
+        let lines = vec![
+            "abc", "", "a", "b", "c", "", "ab", "ac", "", "a", "a", "a", "a", "", "b",
+        ];
+        let report = Report::new(&groups(&lines).unwrap());
+
+        assert_eq!((report.groups, report.people), (5, 11));
+        assert_eq!(
+            report.questions[0],
+            QuestionStats {
+                question: 'a',
+                people: 8,
+                groups: 4
+            }
+        );
+        assert_eq!(report.never_answered.len(), 23);
+        assert_eq!(
+            report.group_sizes.into_iter().collect::<Vec<_>>(),
+            vec![(1, 2), (2, 1), (3, 1), (4, 1)]
+        );
+        assert_eq!(report.unanimous_groups, vec![4]);
+        assert_eq!(report.matching_groups, vec![vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn blank_lines_do_not_make_empty_groups() {
+        let groups = groups(&["", "ab", "", "", "a", ""]).unwrap();
+        assert_eq!(groups.len(), 2);
+
+        let report = Report::new(&groups);
+        assert_eq!(report.groups, 2);
+        assert_eq!(
+            report.group_sizes.into_iter().collect::<Vec<_>>(),
+            vec![(1, 2)]
+        );
     }
 }
@@ -0,0 +1,73 @@
+use crate::graph::{BagGraph, ColorId};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+
+// Which part of the graph to export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope<'a> {
+    All,
+    // The color and every bag it eventually holds
+    Inside(&'a str),
+    // The color and every bag that can eventually hold it
+    Containing(&'a str),
+}
+
+fn selected(graph: &BagGraph, scope: Scope) -> Option<BTreeSet<ColorId>> {
+    match scope {
+        Scope::All => Some(graph.ids().collect()),
+        Scope::Inside(color) => Some(graph.reachable(graph.id(color)?, true)),
+        Scope::Containing(color) => Some(graph.reachable(graph.id(color)?, false)),
+    }
+}
+
+fn edges(graph: &BagGraph, ids: &BTreeSet<ColorId>) -> Vec<(ColorId, usize, ColorId)> {
+    ids.iter()
+        .flat_map(|outer| {
+            graph
+                .contents(*outer)
+                .iter()
+                .filter(|(_, inner)| ids.contains(inner))
+                .map(move |(qty, inner)| (*outer, *qty, *inner))
+        })
+        .collect()
+}
+
+fn quote(color: &str) -> String {
+    format!("\"{}\"", color.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+pub fn to_dot(graph: &BagGraph, scope: Scope) -> Option<String> {
+    let ids = selected(graph, scope)?;
+    let mut dot = String::from("digraph bags {\n");
+    for id in &ids {
+        dot.push_str(&format!("    {};\n", quote(graph.color(*id))));
+    }
+    for (outer, qty, inner) in edges(graph, &ids) {
+        dot.push_str(&format!(
+            "    {} -> {} [label=\"{}\"];\n",
+            quote(graph.color(outer)),
+            quote(graph.color(inner)),
+            qty
+        ));
+    }
+    dot.push_str("}\n");
+    Some(dot)
+}
+
+// `{"outer color": {"inner color": quantity, ...}, ...}`
+pub fn to_json(graph: &BagGraph, scope: Scope) -> Option<Value> {
+    let ids = selected(graph, scope)?;
+    let mut adjacency = ids
+        .iter()
+        .map(|id| (graph.color(*id).to_string(), Value::Object(Map::new())))
+        .collect::<Map<_, _>>();
+    for (outer, qty, inner) in edges(graph, &ids) {
+        let inner_bags = adjacency[graph.color(outer)].as_object_mut().unwrap();
+        let total = inner_bags
+            .get(graph.color(inner))
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        inner_bags.insert(graph.color(inner).to_string(), (total + qty as u64).into());
+    }
+    Some(Value::Object(adjacency))
+}
@@ -0,0 +1,298 @@
+use crate::{OuterBag, ParseError};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+pub type ColorId = usize;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RuleError {
+    // 1-based line of a rule that doesn't follow the grammar
+    Syntax(usize, ParseError),
+    // Path of colors where each one holds the next, ending where it started
+    Cycle(Vec<String>),
+    Undefined {
+        color: String,
+        referenced_by: Vec<String>,
+    },
+    // 1-based lines of every rule for the same color
+    Duplicate {
+        color: String,
+        lines: Vec<usize>,
+    },
+    // Holds nothing and is held by nothing
+    Unreachable(String),
+}
+
+impl RuleError {
+    // Whether counting can't be trusted while this error is present
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, RuleError::Unreachable(_))
+    }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Syntax(line, error) => write!(f, "line {}, {}", line, error),
+            RuleError::Cycle(path) => write!(f, "cycle: {}", path.join(" -> ")),
+            RuleError::Undefined {
+                color,
+                referenced_by,
+            } => write!(
+                f,
+                "{} has no rule but is held by {}",
+                color,
+                referenced_by.join(", ")
+            ),
+            RuleError::Duplicate { color, lines } => {
+                let lines = lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+                write!(f, "{} is defined on lines {}", color, lines.join(", "))
+            }
+            RuleError::Unreachable(color) => write!(f, "{} is never used", color),
+        }
+    }
+}
+
+// Bag rules with every color interned to an index. `contents` holds the
+// (quantity, inner bag) edges of each bag and `containers` the reverse edges.
+#[derive(Debug, Default)]
+pub struct BagGraph {
+    colors: Vec<String>,
+    ids: HashMap<String, ColorId>,
+    contents: Vec<Vec<(usize, ColorId)>>,
+    containers: Vec<Vec<ColorId>>,
+    definitions: Vec<Vec<usize>>,
+    inside_memo: RefCell<HashMap<ColorId, usize>>,
+    ancestors_memo: RefCell<HashMap<ColorId, BTreeSet<ColorId>>>,
+}
+
+impl BagGraph {
+    pub fn new(rules: &[OuterBag]) -> Self {
+        let mut graph = BagGraph::default();
+        for (line, rule) in rules.iter().enumerate() {
+            let outer = graph.intern(&rule.color);
+            graph.definitions[outer].push(line + 1);
+            for inner in &rule.contents {
+                let id = graph.intern(&inner.color);
+                graph.contents[outer].push((inner.qty, id));
+                graph.containers[id].push(outer);
+            }
+        }
+        graph
+    }
+
+    fn intern(&mut self, color: &str) -> ColorId {
+        if let Some(id) = self.ids.get(color) {
+            return *id;
+        }
+        let id = self.colors.len();
+        self.colors.push(color.to_string());
+        self.ids.insert(color.to_string(), id);
+        self.contents.push(vec![]);
+        self.containers.push(vec![]);
+        self.definitions.push(vec![]);
+        id
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<RuleError>> {
+        let mut errors = self.cycles();
+        for id in 0..self.colors.len() {
+            let color = self.color(id).to_string();
+            match self.definitions[id].len() {
+                0 => {
+                    let mut referenced_by = self.containers[id]
+                        .iter()
+                        .map(|c| self.color(*c).to_string())
+                        .collect::<Vec<_>>();
+                    referenced_by.dedup();
+                    errors.push(RuleError::Undefined {
+                        color,
+                        referenced_by,
+                    })
+                }
+                1 => {}
+                _ => errors.push(RuleError::Duplicate {
+                    color,
+                    lines: self.definitions[id].clone(),
+                }),
+            }
+            if self.contents[id].is_empty() && self.containers[id].is_empty() {
+                errors.push(RuleError::Unreachable(self.color(id).to_string()));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // One cycle per back edge found by a depth-first search
+    fn cycles(&self) -> Vec<RuleError> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            New,
+            OnPath,
+            Done,
+        }
+
+        // Iterative so that long chains of bags can't overflow the stack
+        let mut marks = vec![Mark::New; self.colors.len()];
+        let mut cycles = vec![];
+        for start in 0..self.colors.len() {
+            if marks[start] != Mark::New {
+                continue;
+            }
+            let mut path: Vec<(ColorId, usize)> = vec![(start, 0)];
+            marks[start] = Mark::OnPath;
+            while let Some((id, next)) = path.last_mut() {
+                let id = *id;
+                match self.contents[id].get(*next) {
+                    Some((_, inner)) => {
+                        *next += 1;
+                        match marks[*inner] {
+                            Mark::New => {
+                                marks[*inner] = Mark::OnPath;
+                                path.push((*inner, 0));
+                            }
+                            Mark::OnPath => {
+                                let from = path.iter().position(|(c, _)| c == inner).unwrap();
+                                let cycle = path[from..]
+                                    .iter()
+                                    .map(|(c, _)| self.color(*c).to_string())
+                                    .chain(Some(self.color(*inner).to_string()))
+                                    .collect();
+                                cycles.push(RuleError::Cycle(cycle));
+                            }
+                            Mark::Done => {}
+                        }
+                    }
+                    None => {
+                        marks[id] = Mark::Done;
+                        path.pop();
+                    }
+                }
+            }
+        }
+        cycles
+    }
+
+    pub fn id(&self, color: &str) -> Option<ColorId> {
+        self.ids.get(color).copied()
+    }
+
+    pub fn color(&self, id: ColorId) -> &str {
+        &self.colors[id]
+    }
+
+    pub fn ids(&self) -> std::ops::Range<ColorId> {
+        0..self.colors.len()
+    }
+
+    pub fn contents(&self, id: ColorId) -> &[(usize, ColorId)] {
+        &self.contents[id]
+    }
+
+    // Every bag reachable from `id` following contents (`forward`) or
+    // containers, including `id` itself. Safe on cyclic rules.
+    pub fn reachable(&self, id: ColorId, forward: bool) -> BTreeSet<ColorId> {
+        self.walk(vec![id], forward)
+    }
+
+    fn walk(&self, roots: Vec<ColorId>, forward: bool) -> BTreeSet<ColorId> {
+        let mut seen = BTreeSet::new();
+        let mut stack = roots;
+        while let Some(id) = stack.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            if forward {
+                stack.extend(self.contents[id].iter().map(|(_, inner)| *inner));
+            } else {
+                stack.extend(self.containers[id].iter().copied());
+            }
+        }
+        seen
+    }
+
+    // How many bags a bag of `color` must hold, not counting itself. None for
+    // unknown colors and for bags that end up holding themselves.
+    pub fn bags_inside(&self, color: &str) -> Option<usize> {
+        self.id(color).and_then(|id| self.count_inside(id))
+    }
+
+    // Counts each bag after everything inside it, on an explicit stack so that
+    // long chains of bags can't overflow it either
+    fn count_inside(&self, id: ColorId) -> Option<usize> {
+        let mut memo = self.inside_memo.borrow_mut();
+        let mut on_path = BTreeSet::new();
+        let mut path = vec![(id, 0)];
+        on_path.insert(id);
+        while let Some((outer, next)) = path.last_mut() {
+            let outer = *outer;
+            if memo.contains_key(&outer) {
+                on_path.remove(&outer);
+                path.pop();
+                continue;
+            }
+            match self.contents[outer].get(*next) {
+                Some((_, inner)) => {
+                    *next += 1;
+                    if on_path.contains(inner) {
+                        return None;
+                    }
+                    if !memo.contains_key(inner) {
+                        on_path.insert(*inner);
+                        path.push((*inner, 0));
+                    }
+                }
+                None => {
+                    let count = self.contents[outer]
+                        .iter()
+                        .map(|(qty, inner)| qty * (1 + memo[inner]))
+                        .sum();
+                    memo.insert(outer, count);
+                    on_path.remove(&outer);
+                    path.pop();
+                }
+            }
+        }
+        memo.get(&id).copied()
+    }
+
+    // Colors of every bag that can eventually hold a bag of `color`, which
+    // includes `color` itself when it's part of a cycle
+    pub fn containers_of(&self, color: &str) -> Option<Vec<&str>> {
+        let id = self.id(color)?;
+        Some(
+            self.ancestors(id)
+                .into_iter()
+                .map(|id| self.color(id))
+                .collect(),
+        )
+    }
+
+    // Walks up the containers, taking the cached ancestors of any bag already
+    // asked about instead of walking past it: those are ancestors here too
+    fn ancestors(&self, id: ColorId) -> BTreeSet<ColorId> {
+        let mut memo = self.ancestors_memo.borrow_mut();
+        if let Some(ancestors) = memo.get(&id) {
+            return ancestors.clone();
+        }
+        let mut ancestors = BTreeSet::new();
+        let mut stack = self.containers[id].clone();
+        while let Some(parent) = stack.pop() {
+            if !ancestors.insert(parent) {
+                continue;
+            }
+            match memo.get(&parent) {
+                Some(known) => ancestors.extend(known),
+                None => stack.extend(self.containers[parent].iter().copied()),
+            }
+        }
+        memo.insert(id, ancestors.clone());
+        ancestors
+    }
+}
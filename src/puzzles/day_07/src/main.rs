@@ -1,79 +1,118 @@
 extern crate nom;
-use std::usize;
 
+mod export;
+mod graph;
+
+use export::Scope;
+use graph::{BagGraph, RuleError};
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{alpha1, digit1},
-    error::VerboseError,
-    multi::many0,
-    sequence::delimited,
-    sequence::tuple,
-    IResult,
+    bytes::complete::{tag, take_while1},
+    character::complete::{char, digit1},
+    combinator::{cut, eof, map, map_res, recognize, verify},
+    error::{context, VerboseError, VerboseErrorKind},
+    multi::separated_list1,
+    sequence::{preceded, tuple},
+    Err, IResult,
 };
+use std::fmt;
 use utils::read_lines;
 
 type Res<T, U> = IResult<T, U, VerboseError<T>>;
 
-fn solve_p1<T: AsRef<str>>(lines: &Vec<T>) -> usize {
-    let rules = lines
-        .into_iter()
-        .map(OuterBag::from_str)
-        .collect::<Option<Vec<_>>>()
-        .unwrap();
-
-    let mut result: Vec<String> = Vec::new();
-    let mut found: Vec<String> = vec!["shiny gold".to_string()];
-    loop {
-        found = rules
-            .iter()
-            .filter(|bag| {
-                bag.contents
-                    .iter()
-                    .any(|inner| found.contains(&inner.color))
-            })
-            .map(|bag| bag.color.clone())
-            .collect::<Vec<_>>();
-
-        result.append(&mut found.clone());
-
-        if found.is_empty() {
-            break;
+// Every line that fails to parse is reported, not just the first one
+fn parse_graph<T: AsRef<str>>(lines: &[T]) -> Result<BagGraph, Vec<RuleError>> {
+    let mut rules = vec![];
+    let mut errors = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        match OuterBag::parse(line) {
+            Ok(rule) => rules.push(rule),
+            Err(error) => errors.push(RuleError::Syntax(i + 1, error)),
         }
     }
-    result.sort();
-    result.dedup();
+    if errors.is_empty() {
+        Ok(BagGraph::new(&rules))
+    } else {
+        Err(errors)
+    }
+}
 
-    result.len()
+// Refuses to count on rules that don't parse, with cycles, missing or
+// repeated definitions
+fn checked_graph<T: AsRef<str>>(lines: &[T]) -> Result<BagGraph, Vec<RuleError>> {
+    let graph = parse_graph(lines)?;
+    match graph.validate() {
+        Err(errors) if errors.iter().any(RuleError::is_fatal) => Err(errors),
+        _ => Ok(graph),
+    }
 }
 
-fn solve_p2<T: AsRef<str>>(lines: &Vec<T>) -> usize {
-    let rules = lines
-        .into_iter()
-        .map(OuterBag::from_str)
-        .collect::<Option<Vec<_>>>()
-        .unwrap();
+fn solve_p1(graph: &BagGraph, color: &str) -> usize {
+    graph
+        .containers_of(color)
+        .map_or(0, |containers| containers.len())
+}
 
-    rules
-        .iter()
-        .find(|b| b.color == "shiny gold")
-        .unwrap()
-        .contents
-        .iter()
-        .map(|b| b.count_bags(&rules, b.qty))
-        .sum()
+fn solve_p2(graph: &BagGraph, color: &str) -> usize {
+    graph.bags_inside(color).unwrap_or(0)
 }
 
 fn main() {
     let lines = read_lines("src/inputs/day_07.txt")
         .unwrap()
-        .flatten()
+        .map_while(Result::ok)
         .collect::<Vec<_>>();
 
-    let result = solve_p1(&lines);
+    // `day_07 dot|json [--inside COLOR | --containing COLOR]` exports the rules
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(format) = args.get(1).filter(|a| *a == "dot" || *a == "json") {
+        let scope = match (args.get(2).map(|a| a.as_str()), args.get(3)) {
+            (Some("--inside"), Some(color)) => Scope::Inside(color),
+            (Some("--containing"), Some(color)) => Scope::Containing(color),
+            _ => Scope::All,
+        };
+        let graph = match parse_graph(&lines) {
+            Ok(graph) => graph,
+            Err(errors) => {
+                errors.iter().for_each(|error| eprintln!("{}", error));
+                return;
+            }
+        };
+        let exported = if format == "dot" {
+            export::to_dot(&graph, scope)
+        } else {
+            export::to_json(&graph, scope).map(|json| format!("{:#}\n", json))
+        };
+        match exported {
+            Some(out) => print!("{}", out),
+            None => eprintln!("Unknown color"),
+        }
+        return;
+    }
+
+    // `day_07 COLOR` asks about another bag instead of shiny gold
+    let color = args
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| "shiny gold".to_string());
+
+    // Fatal errors leave the parts below unanswered. Both parts share one
+    // graph, so the second can reuse what the first cached.
+    let graph = match checked_graph(&lines) {
+        Ok(graph) => graph,
+        Err(errors) => {
+            errors.iter().for_each(|error| eprintln!("{}", error));
+            return;
+        }
+    };
+    for warning in graph.validate().err().unwrap_or_default() {
+        eprintln!("{}", warning);
+    }
+
+    let result = solve_p1(&graph, &color);
     println!("Part 1: {:?}", result);
 
-    let result = solve_p2(&lines);
+    let result = solve_p2(&graph, &color);
     println!("Part 2: {:?}", result);
 }
 
@@ -83,18 +122,6 @@ pub struct InnerBags {
     qty: usize,
 }
 
-impl InnerBags {
-    pub fn count_bags(&self, rules: &Vec<OuterBag>, prev_count: usize) -> usize {
-        let target = rules.iter().find(|b| b.color == self.color).unwrap();
-        target
-            .contents
-            .iter()
-            .map(|ib| ib.count_bags(&rules, ib.qty * prev_count))
-            .sum::<usize>()
-            + prev_count
-    }
-}
-
 #[derive(Debug, PartialEq, Eq)]
 pub struct OuterBag {
     color: String,
@@ -102,44 +129,113 @@ pub struct OuterBag {
 }
 
 impl OuterBag {
-    pub fn from_str<T: AsRef<str>>(str: T) -> Option<Self> {
-        outer_bag(str.as_ref()).map(|v| v.1).ok()
+    pub fn parse<T: AsRef<str>>(str: T) -> Result<Self, ParseError> {
+        let input = str.as_ref();
+        match outer_bag(input) {
+            Ok((_, bag)) => Ok(bag),
+            Err(Err::Error(e)) | Err(Err::Failure(e)) => Err(ParseError::new(input, e)),
+            Err(Err::Incomplete(_)) => unreachable!("Complete parsers never ask for more input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    // 1-based, in characters
+    column: usize,
+    expected: &'static str,
+    found: Option<char>,
+}
+
+impl ParseError {
+    // Reports the furthest point any branch reached, described by the
+    // outermost label attached there
+    fn new(input: &str, error: VerboseError<&str>) -> Self {
+        let offset = |rest: &str| input.len() - rest.len();
+        let furthest = error
+            .errors
+            .iter()
+            .map(|(rest, _)| offset(rest))
+            .max()
+            .unwrap_or(0);
+        let expected = error
+            .errors
+            .iter()
+            .rev()
+            .filter(|(rest, _)| offset(rest) == furthest)
+            .find_map(|(_, kind)| match kind {
+                VerboseErrorKind::Context(label) => Some(*label),
+                _ => None,
+            })
+            .unwrap_or("a bag rule");
+        ParseError {
+            column: input[..furthest].chars().count() + 1,
+            expected,
+            found: input[furthest..].chars().next(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: expected {}, ", self.column, self.expected)?;
+        match self.found {
+            Some(c) => write!(f, "found {:?}", c),
+            None => write!(f, "found end of line"),
+        }
     }
 }
 
+// Any number of words made of letters and hyphens, stopping before `bag`/`bags`
+fn color(input: &str) -> Res<&str, String> {
+    let word = verify(
+        take_while1(|c: char| c.is_alphabetic() || c == '-'),
+        |w: &str| w != "bag" && w != "bags" && !w.starts_with('-') && !w.ends_with('-'),
+    );
+    context(
+        "a color",
+        map(recognize(separated_list1(char(' '), word)), String::from),
+    )(input)
+}
+
 fn inner_bag(input: &str) -> Res<&str, InnerBags> {
-    let end = alt((tag("."), tag(", ")));
-    let bag = alt((tag("bags"), tag("bag")));
-    let color = tuple((alpha1, tag(" "), alpha1));
-    let mut parser = tuple((digit1, delimited(tag(" "), color, tag(" ")), bag, end));
-
-    parser(input).and_then(|(next, output)| {
-        let res = InnerBags {
-            color: vec![output.1 .0, output.1 .2].join(" "),
-            qty: output.0.parse().expect("Unable to parse number"),
-        };
-        Ok((next, res))
-    })
+    let qty = context("a quantity", map_res(digit1, str::parse::<usize>));
+    let bag = context("\" bag\" or \" bags\"", alt((tag(" bags"), tag(" bag"))));
+    let mut parser = tuple((qty, cut(tuple((preceded(char(' '), color), bag)))));
+
+    parser(input).map(|(next, (qty, (color, _)))| (next, InnerBags { color, qty }))
+}
+
+fn contents(input: &str) -> Res<&str, Vec<InnerBags>> {
+    let nothing = map(tag("no other bags"), |_| vec![]);
+    let bags = separated_list1(tag(", "), cut(inner_bag));
+    context("\"no other bags\" or a quantity", alt((nothing, bags)))(input)
 }
 
 fn outer_bag(input: &str) -> Res<&str, OuterBag> {
-    let color = tuple((alpha1, tag(" "), alpha1));
-    let noise = tag(" bags contain ");
-    let mut parser = tuple((color, noise, many0(inner_bag)));
-
-    parser(input).map(|(next, output)| {
-        let res = OuterBag {
-            color: vec![output.0 .0, output.0 .2].join(" "),
-            contents: output.2,
-        };
-        (next, res)
-    })
+    let noise = context("\" bags contain \"", tag(" bags contain "));
+    let end = tuple((context("\".\"", char('.')), context("end of line", eof)));
+    let mut parser = tuple((color, noise, contents, end));
+
+    parser(input).map(|(next, (color, _, contents, _))| (next, OuterBag { color, contents }))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    static EXAMPLE: [&str; 9] = [
+        "light red bags contain 1 bright white bag, 2 muted yellow bags.",
+        "dark orange bags contain 3 bright white bags, 4 muted yellow bags.",
+        "bright white bags contain 1 shiny gold bag.",
+        "muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.",
+        "shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.",
+        "dark olive bags contain 3 faded blue bags, 4 dotted black bags.",
+        "vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.",
+        "faded blue bags contain no other bags.",
+        "dotted black bags contain no other bags.",
+    ];
+
     #[test]
     fn inner_bag_examples() {
         let result = inner_bag("1 bright white bag, ").unwrap().1;
@@ -154,7 +250,7 @@ mod test {
 
     #[test]
     fn multi_inner_bags_examples() {
-        let result = many0(inner_bag)("1 bright white bag, 2 muted yellow bags.")
+        let result = contents("1 bright white bag, 2 muted yellow bags.")
             .unwrap()
             .1;
         assert_eq!(
@@ -209,18 +305,8 @@ mod test {
 
     #[test]
     fn example_p1() {
-        let input = vec![
-            "light red bags contain 1 bright white bag, 2 muted yellow bags.",
-            "dark orange bags contain 3 bright white bags, 4 muted yellow bags.",
-            "bright white bags contain 1 shiny gold bag.",
-            "muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.",
-            "shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.",
-            "dark olive bags contain 3 faded blue bags, 4 dotted black bags.",
-            "vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.",
-            "faded blue bags contain no other bags.",
-            "dotted black bags contain no other bags.",
-        ];
-        assert_eq!(solve_p1(&input), 4);
+        let input = EXAMPLE;
+        assert_eq!(solve_p1(&checked_graph(&input).unwrap(), "shiny gold"), 4);
     }
 
     #[test]
@@ -234,6 +320,235 @@ mod test {
             "dark blue bags contain 2 dark violet bags.",
             "dark violet bags contain no other bags.",
         ];
-        assert_eq!(solve_p2(&input), 126);
+        assert_eq!(solve_p2(&checked_graph(&input).unwrap(), "shiny gold"), 126);
+    }
+
+    #[test]
+    fn graph_queries_any_color() {
+        let input = EXAMPLE;
+        let graph = parse_graph(&input).unwrap();
+
+        assert_eq!(graph.bags_inside("shiny gold"), Some(32));
+        assert_eq!(graph.bags_inside("dark olive"), Some(7));
+        assert_eq!(graph.bags_inside("faded blue"), Some(0));
+        assert_eq!(graph.bags_inside("plaid purple"), None);
+        assert_eq!(
+            graph.containers_of("muted yellow"),
+            Some(vec!["light red", "dark orange"])
+        );
+        assert_eq!(graph.containers_of("light red"), Some(vec![]));
+    }
+
+    #[test]
+    fn validation_errors() {
+        let input = vec![
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.",
+            "bright white bags contain 1 shiny gold bag.",
+            "shiny gold bags contain 2 light red bags.",
+            "muted yellow bags contain 3 faded blue bags.",
+            "muted yellow bags contain no other bags.",
+            "dotted black bags contain no other bags.",
+        ];
+        let errors = parse_graph(&input).unwrap().validate().unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                RuleError::Cycle(vec![
+                    "light red".to_string(),
+                    "bright white".to_string(),
+                    "shiny gold".to_string(),
+                    "light red".to_string(),
+                ]),
+                RuleError::Duplicate {
+                    color: "muted yellow".to_string(),
+                    lines: vec![4, 5],
+                },
+                RuleError::Undefined {
+                    color: "faded blue".to_string(),
+                    referenced_by: vec!["muted yellow".to_string()],
+                },
+                RuleError::Unreachable("dotted black".to_string()),
+            ]
+        );
+        assert_eq!(checked_graph(&input).err(), Some(errors));
+    }
+
+    #[test]
+    fn unvalidated_queries_survive_cycles() {
+        let input = vec![
+            "a b bags contain 1 c d bag.",
+            "c d bags contain 2 a b bags.",
+            "e f bags contain 3 a b bags.",
+            "g h bags contain no other bags.",
+        ];
+        let graph = parse_graph(&input).unwrap();
+        assert_eq!(graph.bags_inside("a b"), None);
+        assert_eq!(graph.bags_inside("e f"), None);
+        assert_eq!(graph.bags_inside("g h"), Some(0));
+        assert_eq!(graph.containers_of("a b"), Some(vec!["a b", "c d", "e f"]));
+        // Answered partly from the cache of the query above
+        assert_eq!(graph.containers_of("c d"), Some(vec!["a b", "c d", "e f"]));
+
+        // Deep enough to overflow a recursive count. Colors are words of
+        // letters, so number them with the digits q..z, which can't spell
+        // "bag".
+        let name = |mut i: usize| {
+            let mut word = String::new();
+            loop {
+                word.push((b'q' + (i % 10) as u8) as char);
+                i /= 10;
+                if i == 0 {
+                    return format!("deep {}", word);
+                }
+            }
+        };
+        let mut input = (0..100_000)
+            .map(|i| format!("{} bags contain 1 {} bag.", name(i), name(i + 1)))
+            .collect::<Vec<_>>();
+        input.push(format!("{} bags contain no other bags.", name(100_000)));
+        let graph = parse_graph(&input).unwrap();
+        assert_eq!(graph.bags_inside(&name(0)), Some(100_000));
+        assert_eq!(
+            graph.containers_of(&name(100_000)).map(|c| c.len()),
+            Some(100_000)
+        );
+    }
+
+    #[test]
+    fn unreachable_bags_do_not_block_counting() {
+        let input = vec![
+            "shiny gold bags contain 2 dark red bags.",
+            "dark red bags contain no other bags.",
+            "dotted black bags contain no other bags.",
+        ];
+        assert_eq!(solve_p2(&checked_graph(&input).unwrap(), "shiny gold"), 2);
+    }
+
+    // Small enough to check whole exports against
+    static EXPORT_RULES: [&str; 6] = [
+        "light red bags contain 1 bright white bag, 2 muted yellow bags.",
+        "bright white bags contain 1 shiny gold bag.",
+        "muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.",
+        "shiny gold bags contain 1 dark olive bag.",
+        "faded blue bags contain no other bags.",
+        "dark olive bags contain no other bags.",
+    ];
+
+    #[test]
+    fn dot_export() {
+        let input = EXPORT_RULES;
+        let graph = parse_graph(&input).unwrap();
+
+        assert_eq!(
+            export::to_dot(&graph, Scope::Inside("muted yellow")).unwrap(),
+            [
+                "digraph bags {",
+                "    \"muted yellow\";",
+                "    \"shiny gold\";",
+                "    \"faded blue\";",
+                "    \"dark olive\";",
+                "    \"muted yellow\" -> \"shiny gold\" [label=\"2\"];",
+                "    \"muted yellow\" -> \"faded blue\" [label=\"9\"];",
+                "    \"shiny gold\" -> \"dark olive\" [label=\"1\"];",
+                "}",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(export::to_dot(&graph, Scope::Inside("plaid purple")), None);
+    }
+
+    #[test]
+    fn json_export() {
+        let input = EXPORT_RULES;
+        let graph = parse_graph(&input).unwrap();
+
+        assert_eq!(
+            export::to_json(&graph, Scope::Containing("shiny gold")).unwrap(),
+            serde_json::json!({
+                "light red": { "bright white": 1, "muted yellow": 2 },
+                "bright white": { "shiny gold": 1 },
+                "muted yellow": { "shiny gold": 2 },
+                "shiny gold": {},
+            })
+        );
+        assert_eq!(
+            export::to_json(&graph, Scope::All).unwrap()["muted yellow"],
+            serde_json::json!({ "shiny gold": 2, "faded blue": 9 })
+        );
+    }
+
+    #[test]
+    fn colors_of_any_length() {
+        let result =
+            OuterBag::parse("pale blue-green bags contain 3 dark bags, 1 very shiny sky-blue bag.");
+        assert_eq!(
+            result,
+            Ok(OuterBag {
+                color: "pale blue-green".to_string(),
+                contents: vec![
+                    InnerBags {
+                        color: "dark".to_string(),
+                        qty: 3,
+                    },
+                    InnerBags {
+                        color: "very shiny sky-blue".to_string(),
+                        qty: 1,
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn parse_error_columns() {
+        let error = |line: &str| OuterBag::parse(line).unwrap_err().to_string();
+
+        assert_eq!(
+            error("light red bags contain 1 bright white bag, 2 muted yellow bags"),
+            "column 63: expected \".\", found end of line"
+        );
+        assert_eq!(
+            error("light red bags contain 1 bright white bag, 2 muted yellow bags. extra"),
+            "column 64: expected end of line, found ' '"
+        );
+        assert_eq!(
+            error("light red bags contain 1 bright white bag, two muted yellow bags."),
+            "column 44: expected a quantity, found 't'"
+        );
+        assert_eq!(
+            error("light red bags contain 1 bright white box."),
+            "column 42: expected \" bag\" or \" bags\", found '.'"
+        );
+        assert_eq!(
+            error("light red bags hold 1 bright white bag."),
+            "column 10: expected \" bags contain \", found ' '"
+        );
+        assert_eq!(
+            error("faded blue bags contain no bags."),
+            "column 25: expected \"no other bags\" or a quantity, found 'n'"
+        );
+        assert_eq!(
+            error("-red bags contain no other bags."),
+            "column 1: expected a color, found '-'"
+        );
+    }
+
+    #[test]
+    fn syntax_errors_name_every_line() {
+        let input = vec![
+            "shiny gold bags contain 2 dark red bags.",
+            "dark red bags contain nothing.",
+            "dotted black bags contain no other bags",
+        ];
+        let errors = parse_graph(&input).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec![
+                "line 2, column 23: expected \"no other bags\" or a quantity, found 'n'",
+                "line 3, column 40: expected \".\", found end of line",
+            ]
+        );
+        assert_eq!(checked_graph(&input).err(), Some(errors));
     }
 }
@@ -0,0 +1,176 @@
+use crate::{Flow, Instruction, InstructionSet, ParseError, Program};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+// Lines are 1-based lines of the assembly source
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AsmError {
+    Parse(ParseError),
+    InvalidLabel {
+        line: usize,
+        label: String,
+    },
+    DuplicateLabel {
+        line: usize,
+        label: String,
+        first: usize,
+    },
+    UndefinedLabel {
+        line: usize,
+        label: String,
+    },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::Parse(e) => write!(f, "{}", e),
+            AsmError::InvalidLabel { line, label } => {
+                write!(f, "line {}: invalid label {:?}", line, label)
+            }
+            AsmError::DuplicateLabel { line, label, first } => write!(
+                f,
+                "line {}: label {:?} already defined on line {}",
+                line, label, first
+            ),
+            AsmError::UndefinedLabel { line, label } => {
+                write!(f, "line {}: undefined label {:?}", line, label)
+            }
+        }
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Reads the day's instruction format extended with `; comments` and
+// `label:` definitions, either alone on a line or before an instruction.
+// Any operand may name a label instead, which becomes the offset to it.
+pub fn assemble(set: &InstructionSet, source: &str) -> Result<Program, Vec<AsmError>> {
+    let mut errors = vec![];
+    // label -> (address, source line)
+    let mut labels = HashMap::new();
+    // (source line, code) for every instruction, in address order
+    let mut statements = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let mut code = line.split(';').next().unwrap_or_default().trim();
+        while let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                errors.push(AsmError::InvalidLabel {
+                    line: line_no,
+                    label: label.to_string(),
+                });
+            } else if let Some((_, first)) = labels.get(label) {
+                errors.push(AsmError::DuplicateLabel {
+                    line: line_no,
+                    label: label.to_string(),
+                    first: *first,
+                });
+            } else {
+                labels.insert(label.to_string(), (statements.len(), line_no));
+            }
+            code = rest.trim();
+        }
+        if !code.is_empty() {
+            statements.push((line_no, code));
+        }
+    }
+
+    let mut instructions = vec![];
+    for (address, (line, code)) in statements.into_iter().enumerate() {
+        let mut words = code.split_whitespace();
+        let opcode = words.next().unwrap_or_default();
+        let operand = words.next().unwrap_or_default();
+        // Operands naming a label are swapped for a placeholder offset so the
+        // rest goes through the regular parser
+        let label = if is_label(operand) {
+            match labels.get(operand) {
+                Some((target, _)) => Some(*target as i32 - address as i32),
+                None => {
+                    errors.push(AsmError::UndefinedLabel {
+                        line,
+                        label: operand.to_string(),
+                    });
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        let text = match label {
+            Some(_) => format!("{} +0", opcode),
+            None => code.to_string(),
+        };
+        match set.parse_instruction(line, &text) {
+            Ok(instruction) => {
+                let arg = label.unwrap_or(instruction.arg);
+                instructions.push(Instruction::new(instruction.op, arg));
+            }
+            Err(e) => errors.push(AsmError::Parse(e)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(Program::new(instructions))
+    } else {
+        errors.sort_by_key(|e| match e {
+            AsmError::Parse(
+                ParseError::UnknownOpcode { line, .. }
+                | ParseError::MissingOperand(line)
+                | ParseError::InvalidOperand { line, .. },
+            )
+            | AsmError::InvalidLabel { line, .. }
+            | AsmError::DuplicateLabel { line, .. }
+            | AsmError::UndefinedLabel { line, .. } => *line,
+        });
+        Err(errors)
+    }
+}
+
+// Writes jumps that stay within the program (or land right after it) against
+// `L0`, `L1`, ... labels numbered by address; other operands stay numeric
+pub fn disassemble(program: &Program) -> String {
+    let len = program.instructions.len();
+    let target = |address: usize, instruction: &Instruction| {
+        let target = address as isize + instruction.arg as isize;
+        if instruction.op.flow != Flow::Next && (0..=len as isize).contains(&target) {
+            Some(target as usize)
+        } else {
+            None
+        }
+    };
+    let names = program
+        .instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(address, instruction)| target(address, instruction))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .enumerate()
+        .map(|(n, address)| (address, format!("L{}", n)))
+        .collect::<HashMap<_, _>>();
+
+    let mut out = String::new();
+    for address in 0..=len {
+        if let Some(name) = names.get(&address) {
+            out.push_str(&format!("{}:\n", name));
+        }
+        let instruction = match program.instructions.get(address) {
+            Some(instruction) => instruction,
+            None => break,
+        };
+        match target(address, instruction) {
+            Some(to) => out.push_str(&format!("    {} {}\n", instruction.op.name, names[&to])),
+            None => out.push_str(&format!("    {}\n", instruction)),
+        }
+    }
+    out
+}
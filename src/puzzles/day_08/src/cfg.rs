@@ -0,0 +1,230 @@
+use crate::{BitSet, Flow, Instruction, Program};
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum Target {
+    Block(usize),
+    // Right after the last instruction, where the program ends correctly
+    Exit,
+    OutOfBounds(isize),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Block {
+    pub lines: Range<usize>,
+    pub successors: Vec<Target>,
+    pub predecessors: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Finding {
+    // Never run when starting from the first line
+    Unreachable(Range<usize>),
+    // Reachable, but every path from here stays in the program forever
+    InfiniteLoop(Range<usize>),
+    OutOfBounds { line: usize, target: isize },
+}
+
+impl Finding {
+    pub fn line(&self) -> usize {
+        match self {
+            Finding::Unreachable(lines) | Finding::InfiniteLoop(lines) => lines.start,
+            Finding::OutOfBounds { line, .. } => *line,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::Unreachable(lines) => write!(f, "lines {:?}: unreachable", lines),
+            Finding::InfiniteLoop(lines) => write!(f, "lines {:?}: never terminate", lines),
+            Finding::OutOfBounds { line, target } => {
+                write!(f, "line {}: jumps out of bounds to {}", line, target)
+            }
+        }
+    }
+}
+
+// Basic blocks of a program: straight runs of lines that are only entered at
+// the top and only jump away at the bottom
+pub struct Cfg {
+    instructions: Vec<Instruction>,
+    blocks: Vec<Block>,
+}
+
+impl Cfg {
+    pub fn new(program: &Program) -> Self {
+        let instructions = program.instructions.clone();
+        let len = instructions.len();
+
+        // A block starts at the first line, at every jump target and right
+        // after every jump
+        let mut leaders = BitSet::new(len);
+        leaders.insert(0);
+        for (line, instruction) in instructions.iter().enumerate() {
+            if instruction.op.flow != Flow::Next {
+                leaders.insert(line + 1);
+                for s in instruction.successors(line) {
+                    if (0..len as isize).contains(&s) {
+                        leaders.insert(s as usize);
+                    }
+                }
+            }
+        }
+        let starts = (0..len)
+            .filter(|l| leaders.contains(*l))
+            .collect::<Vec<_>>();
+        let mut block_of = vec![0; len];
+        let mut blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(id, start)| {
+                let end = starts.get(id + 1).copied().unwrap_or(len);
+                block_of[*start..end].iter_mut().for_each(|b| *b = id);
+                Block {
+                    lines: *start..end,
+                    successors: vec![],
+                    predecessors: vec![],
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for id in 0..blocks.len() {
+            let last = blocks[id].lines.end - 1;
+            let mut successors = instructions[last]
+                .successors(last)
+                .into_iter()
+                .map(|s| match s {
+                    s if s == len as isize => Target::Exit,
+                    s if s < 0 || s > len as isize => Target::OutOfBounds(s),
+                    s => Target::Block(block_of[s as usize]),
+                })
+                .collect::<Vec<_>>();
+            successors.sort();
+            successors.dedup();
+            for s in &successors {
+                if let Target::Block(to) = s {
+                    blocks[*to].predecessors.push(id);
+                }
+            }
+            blocks[id].successors = successors;
+        }
+
+        Cfg {
+            instructions,
+            blocks,
+        }
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    // Blocks a walk over `edges` visits starting from `roots`
+    fn walk<F: Fn(&Block) -> Vec<usize>>(&self, roots: Vec<usize>, edges: F) -> BitSet {
+        let mut seen = BitSet::new(self.blocks.len());
+        let mut stack = roots;
+        while let Some(id) = stack.pop() {
+            if seen.insert(id) {
+                stack.extend(edges(&self.blocks[id]));
+            }
+        }
+        seen
+    }
+
+    pub fn reachable(&self) -> BitSet {
+        let roots = if self.blocks.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        self.walk(roots, |block| {
+            block
+                .successors
+                .iter()
+                .filter_map(|s| match s {
+                    Target::Block(id) => Some(*id),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    // Blocks with some path out of the program, correct or not
+    fn escaping(&self) -> BitSet {
+        let roots = (0..self.blocks.len())
+            .filter(|id| {
+                self.blocks[*id]
+                    .successors
+                    .iter()
+                    .any(|s| !matches!(s, Target::Block(_)))
+            })
+            .collect();
+        self.walk(roots, |block| block.predecessors.clone())
+    }
+
+    pub fn findings(&self) -> Vec<Finding> {
+        let reachable = self.reachable();
+        let escaping = self.escaping();
+        let mut findings = vec![];
+        for (id, block) in self.blocks.iter().enumerate() {
+            if !reachable.contains(id) {
+                findings.push(Finding::Unreachable(block.lines.clone()));
+            } else if !escaping.contains(id) {
+                findings.push(Finding::InfiniteLoop(block.lines.clone()));
+            }
+            let last = block.lines.end - 1;
+            for s in &block.successors {
+                if let Target::OutOfBounds(target) = s {
+                    findings.push(Finding::OutOfBounds {
+                        line: last,
+                        target: *target,
+                    });
+                }
+            }
+        }
+        findings.sort_by_key(Finding::line);
+        findings
+    }
+
+    // Unreachable blocks are dashed and looping ones red
+    pub fn to_dot(&self) -> String {
+        let reachable = self.reachable();
+        let escaping = self.escaping();
+        let mut dot = String::from("digraph cfg {\n    node [shape=box fontname=monospace];\n");
+        for (id, block) in self.blocks.iter().enumerate() {
+            let label = block
+                .lines
+                .clone()
+                .map(|l| format!("{}: {}\\l", l, self.instructions[l]))
+                .collect::<String>();
+            let style = if !reachable.contains(id) {
+                " style=dashed"
+            } else if !escaping.contains(id) {
+                " color=red"
+            } else {
+                ""
+            };
+            dot.push_str(&format!("    b{} [label=\"{}\"{}];\n", id, label, style));
+        }
+        dot.push_str("    exit [shape=doublecircle];\n");
+        for (id, block) in self.blocks.iter().enumerate() {
+            for s in &block.successors {
+                let to = match s {
+                    Target::Block(to) => format!("b{}", to),
+                    Target::Exit => "exit".to_string(),
+                    Target::OutOfBounds(target) => {
+                        let node = format!("\"out {}\"", target);
+                        dot.push_str(&format!("    {} [shape=octagon];\n", node));
+                        node
+                    }
+                };
+                dot.push_str(&format!("    b{} -> {};\n", id, to));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
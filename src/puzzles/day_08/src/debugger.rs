@@ -0,0 +1,377 @@
+use crate::{FinishCondition, Flow, Program};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    pub fn holds(&self, a: i32, b: i32) -> bool {
+        match self {
+            Cmp::Eq => a == b,
+            Cmp::Ne => a != b,
+            Cmp::Lt => a < b,
+            Cmp::Le => a <= b,
+            Cmp::Gt => a > b,
+            Cmp::Ge => a >= b,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Cmp::Eq => "==",
+            Cmp::Ne => "!=",
+            Cmp::Lt => "<",
+            Cmp::Le => "<=",
+            Cmp::Gt => ">",
+            Cmp::Ge => ">=",
+        }
+    }
+}
+
+impl FromStr for Cmp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Cmp::Eq, Cmp::Ne, Cmp::Lt, Cmp::Le, Cmp::Gt, Cmp::Ge]
+            .iter()
+            .find(|cmp| cmp.symbol() == s)
+            .copied()
+            .ok_or_else(|| format!("Unknown comparison {:?}", s))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Breakpoint {
+    // Stops before the instruction at this ip runs
+    Line(usize),
+    Acc(Cmp, i32),
+}
+
+impl Breakpoint {
+    fn hit(&self, program: &Program) -> bool {
+        match self {
+            Breakpoint::Line(line) => program.ip == *line as isize,
+            Breakpoint::Acc(cmp, value) => cmp.holds(program.acc, *value),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "ip == {}", line),
+            Breakpoint::Acc(cmp, value) => write!(f, "acc {} {}", cmp.symbol(), value),
+        }
+    }
+}
+
+// Values that stop execution whenever they change
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Watch {
+    Acc,
+    // Number of values written by `out`
+    Output,
+}
+
+impl Watch {
+    fn read(&self, program: &Program) -> i32 {
+        match self {
+            Watch::Acc => program.acc,
+            Watch::Output => program.output.len() as i32,
+        }
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watch::Acc => write!(f, "acc"),
+            Watch::Output => write!(f, "output"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(Breakpoint),
+    Watch { watch: Watch, old: i32, new: i32 },
+    Halted(FinishCondition),
+    // The machine is back in a state it already ran from
+    InfiniteLoop,
+    // Nothing left to undo
+    StartOfHistory,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Stepped => Ok(()),
+            Stop::Breakpoint(bp) => write!(f, "breakpoint: {}", bp),
+            Stop::Watch { watch, old, new } => write!(f, "{} changed: {} -> {}", watch, old, new),
+            Stop::Halted(condition) => write!(f, "halted: {:?}", condition),
+            Stop::InfiniteLoop => write!(f, "infinite loop"),
+            Stop::StartOfHistory => write!(f, "start of history"),
+        }
+    }
+}
+
+// Machine state right before a step, enough to undo it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Snapshot {
+    ip: isize,
+    acc: i32,
+    output: usize,
+}
+
+pub struct Debugger {
+    program: Program,
+    history: Vec<Snapshot>,
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<Watch>,
+}
+
+impl Debugger {
+    pub fn new(program: Program) -> Self {
+        Debugger {
+            program,
+            history: vec![],
+            breakpoints: vec![],
+            watches: vec![],
+        }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|bp| *bp != breakpoint);
+        self.breakpoints.len() != len
+    }
+
+    pub fn watch(&mut self, watch: Watch) {
+        if !self.watches.contains(&watch) {
+            self.watches.push(watch);
+        }
+    }
+
+    // Lines run so far, oldest first
+    pub fn history(&self) -> impl Iterator<Item = usize> + '_ {
+        self.history.iter().map(|s| s.ip as usize)
+    }
+
+    pub fn step(&mut self) -> Stop {
+        if let Some(condition) = self.program.halted() {
+            return Stop::Halted(condition);
+        }
+        self.history.push(Snapshot {
+            ip: self.program.ip,
+            acc: self.program.acc,
+            output: self.program.output.len(),
+        });
+        match self.program.step() {
+            Some(condition) => Stop::Halted(condition),
+            None => Stop::Stepped,
+        }
+    }
+
+    pub fn step_back(&mut self) -> Stop {
+        match self.history.pop() {
+            Some(Snapshot { ip, acc, output }) => {
+                self.program.ip = ip;
+                self.program.acc = acc;
+                self.program.output.truncate(output);
+                if let Some(trace) = &mut self.program.trace {
+                    trace.pop();
+                }
+                Stop::Stepped
+            }
+            None => Stop::StartOfHistory,
+        }
+    }
+
+    // Runs until a breakpoint, a watch or the end of the program. Without
+    // conditional jumps the ip alone decides where execution goes next, so
+    // reaching a line twice is a loop; otherwise the accumulator counts too.
+    pub fn cont(&mut self) -> Stop {
+        let branches = self
+            .program
+            .instructions
+            .iter()
+            .any(|i| i.op.flow == Flow::Branch);
+        let mut seen = HashSet::new();
+        loop {
+            let acc = if branches { self.program.acc } else { 0 };
+            if !seen.insert((self.program.ip, acc)) {
+                return Stop::InfiniteLoop;
+            }
+            let before = self
+                .watches
+                .iter()
+                .map(|w| w.read(&self.program))
+                .collect::<Vec<_>>();
+            if let Stop::Halted(condition) = self.step() {
+                return Stop::Halted(condition);
+            }
+            for (watch, old) in self.watches.iter().zip(before) {
+                let new = watch.read(&self.program);
+                if new != old {
+                    return Stop::Watch {
+                        watch: *watch,
+                        old,
+                        new,
+                    };
+                }
+            }
+            if let Some(bp) = self.breakpoints.iter().find(|bp| bp.hit(&self.program)) {
+                return Stop::Breakpoint(*bp);
+            }
+        }
+    }
+
+    // `ip 4  acc 1  | jmp -3`
+    pub fn current(&self) -> String {
+        let state = format!("ip {}  acc {}", self.program.ip, self.program.acc);
+        match self.program.instructions.get(self.program.ip as usize) {
+            Some(instruction) => format!("{}  | {}", state, instruction),
+            _ => format!("{}  | <halted>", state),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Command {
+    Step(usize),
+    Back(usize),
+    Continue,
+    Break(Breakpoint),
+    Delete(Breakpoint),
+    Watch(Watch),
+    Print,
+    History,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        let count = |word: Option<&&str>| match word {
+            Some(n) => n.parse().map_err(|_| format!("Invalid count {:?}", n)),
+            None => Ok(1),
+        };
+        let breakpoint = |args: &[&str]| match args {
+            [line] => line
+                .parse()
+                .map(Breakpoint::Line)
+                .map_err(|_| format!("Invalid line {:?}", line)),
+            ["acc", cmp, value] => Ok(Breakpoint::Acc(
+                cmp.parse()?,
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid value {:?}", value))?,
+            )),
+            _ => Err("Expected `LINE` or `acc CMP VALUE`".to_string()),
+        };
+        match words.as_slice() {
+            ["s" | "step", rest @ ..] => Ok(Command::Step(count(rest.first())?)),
+            ["r" | "back", rest @ ..] => Ok(Command::Back(count(rest.first())?)),
+            ["c" | "continue"] => Ok(Command::Continue),
+            ["b" | "break", args @ ..] => Ok(Command::Break(breakpoint(args)?)),
+            ["d" | "delete", args @ ..] => Ok(Command::Delete(breakpoint(args)?)),
+            ["w" | "watch", "acc"] => Ok(Command::Watch(Watch::Acc)),
+            ["w" | "watch", "output"] => Ok(Command::Watch(Watch::Output)),
+            ["p" | "print"] => Ok(Command::Print),
+            ["h" | "history"] => Ok(Command::History),
+            ["q" | "quit"] => Ok(Command::Quit),
+            _ => Err(format!("Unknown command {:?}", s)),
+        }
+    }
+}
+
+// Reads one command per line until `quit` or the end of the input
+pub fn session<R: BufRead, W: Write>(
+    debugger: &mut Debugger,
+    input: R,
+    mut out: W,
+) -> io::Result<()> {
+    writeln!(out, "{}", debugger.current())?;
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match line.parse::<Command>() {
+            Ok(command) => command,
+            Err(e) => {
+                writeln!(out, "{}", e)?;
+                continue;
+            }
+        };
+        let stop = match command {
+            Command::Step(n) => (0..n)
+                .map(|_| debugger.step())
+                .find(|stop| *stop != Stop::Stepped),
+            Command::Back(n) => (0..n)
+                .map(|_| debugger.step_back())
+                .find(|stop| *stop != Stop::Stepped),
+            Command::Continue => Some(debugger.cont()),
+            Command::Break(bp) => {
+                debugger.add_breakpoint(bp);
+                writeln!(out, "breakpoint set: {}", bp)?;
+                continue;
+            }
+            Command::Delete(bp) => {
+                if !debugger.remove_breakpoint(bp) {
+                    writeln!(out, "no breakpoint {}", bp)?;
+                }
+                continue;
+            }
+            Command::Watch(watch) => {
+                debugger.watch(watch);
+                writeln!(out, "watching {}", watch)?;
+                continue;
+            }
+            Command::Print => {
+                let output = debugger.program().output();
+                if !output.is_empty() {
+                    writeln!(out, "output: {:?}", output)?;
+                }
+                None
+            }
+            Command::History => {
+                let lines = debugger
+                    .history()
+                    .map(|l| l.to_string())
+                    .collect::<Vec<_>>();
+                writeln!(out, "{}", lines.join(" "))?;
+                continue;
+            }
+            Command::Quit => break,
+        };
+        if let Some(stop) = stop {
+            writeln!(out, "{}", stop)?;
+        }
+        writeln!(out, "{}", debugger.current())?;
+    }
+    Ok(())
+}
@@ -1,143 +1,891 @@
+mod asm;
+mod cfg;
+mod debugger;
+mod repair;
+mod trace;
+
+use debugger::Debugger;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use trace::TraceStep;
+use utils::export::Format;
 use utils::read_lines;
 
+// Where an instruction may send the ip, known without running it
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum Instruction {
-    Acc(i32),
-    Jmp(i32),
-    Nop(i32),
+pub enum Flow {
+    Next,
+    Jump,
+    // Either the next line or the offset, depending on the machine state
+    Branch,
 }
 
+// Where an instruction actually sent the ip
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum FinishCondition {
-    Correctly,
-    InfiniteLoop,
-    Overflow,
+pub enum Goto {
+    Next,
+    Offset(i32),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-struct Program {
-    instructions: Vec<Instruction>,
-    ip: usize,
-    acc: i32,
-    lines_executed: Vec<usize>,
+pub type Exec = fn(&mut Program, i32) -> Goto;
+
+#[derive(Clone, Copy)]
+pub struct Opcode {
+    pub name: &'static str,
+    pub flow: Flow,
+    exec: Exec,
 }
 
-impl Program {
-    pub fn run_safe(&mut self) -> (i32, FinishCondition) {
-        if self.lines_executed.contains(&self.ip) {
-            (self.acc, FinishCondition::InfiniteLoop)
-        } else if self.ip == self.instructions.len() - 1 {
-            (self.acc, FinishCondition::Correctly)
-        } else if self.ip >= self.instructions.len() {
-            (self.acc, FinishCondition::Overflow)
+// Opcodes are told apart by mnemonic
+impl PartialEq for Opcode {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Opcode {}
+
+impl fmt::Debug for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub const ACC: Opcode = Opcode {
+    name: "acc",
+    flow: Flow::Next,
+    exec: |p, arg| {
+        p.acc = p.acc.wrapping_add(arg);
+        Goto::Next
+    },
+};
+
+pub const JMP: Opcode = Opcode {
+    name: "jmp",
+    flow: Flow::Jump,
+    exec: |_, arg| Goto::Offset(arg),
+};
+
+pub const NOP: Opcode = Opcode {
+    name: "nop",
+    flow: Flow::Next,
+    exec: |_, _| Goto::Next,
+};
+
+pub const MUL: Opcode = Opcode {
+    name: "mul",
+    flow: Flow::Next,
+    exec: |p, arg| {
+        p.acc = p.acc.wrapping_mul(arg);
+        Goto::Next
+    },
+};
+
+// Jumps only while the accumulator is zero
+pub const JZ: Opcode = Opcode {
+    name: "jz",
+    flow: Flow::Branch,
+    exec: |p, arg| {
+        if p.acc == 0 {
+            Goto::Offset(arg)
         } else {
-            self.lines_executed.push(self.ip);
-            match self.instructions[self.ip] {
-                Instruction::Acc(v) => {
-                    self.acc += v;
-                    self.ip += 1;
-                }
-                Instruction::Jmp(v) => {
-                    self.ip = ((self.ip as i32) + v) as usize;
-                }
-                Instruction::Nop(_) => {
-                    self.ip += 1;
-                }
-            };
-            self.run_safe()
+            Goto::Next
+        }
+    },
+};
+
+// Emits the accumulator; the operand is ignored
+pub const OUT: Opcode = Opcode {
+    name: "out",
+    flow: Flow::Next,
+    exec: |p, _| {
+        p.output.push(p.acc);
+        Goto::Next
+    },
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseError {
+    UnknownOpcode { line: usize, opcode: String },
+    MissingOperand(usize),
+    InvalidOperand { line: usize, operand: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownOpcode { line, opcode } => {
+                write!(f, "line {}: unknown opcode {:?}", line, opcode)
+            }
+            ParseError::MissingOperand(line) => write!(f, "line {}: missing operand", line),
+            ParseError::InvalidOperand { line, operand } => {
+                write!(f, "line {}: invalid operand {:?}", line, operand)
+            }
         }
     }
+}
+
+// Mnemonics the parser understands. Registering an existing name replaces it.
+#[derive(Debug, Clone)]
+pub struct InstructionSet {
+    opcodes: HashMap<&'static str, Opcode>,
+}
 
-    pub fn replace_instruction(&mut self, i: usize) {
-        match self.instructions[i] {
-            Instruction::Nop(val) => self.instructions[i] = Instruction::Jmp(val),
-            Instruction::Jmp(val) => self.instructions[i] = Instruction::Nop(val),
-            _ => {}
+impl Default for InstructionSet {
+    fn default() -> Self {
+        InstructionSet {
+            opcodes: HashMap::new(),
         }
+        .register(ACC)
+        .register(JMP)
+        .register(NOP)
     }
 }
 
-impl<T: AsRef<str>> From<T> for Program {
-    fn from(lines: T) -> Self {
-        let instructions = lines
+impl InstructionSet {
+    pub fn extended() -> Self {
+        InstructionSet::default()
+            .register(MUL)
+            .register(JZ)
+            .register(OUT)
+    }
+
+    pub fn register(mut self, opcode: Opcode) -> Self {
+        self.opcodes.insert(opcode.name, opcode);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<Opcode> {
+        self.opcodes.get(name).copied()
+    }
+
+    // `line` is 1-based and only used to report errors
+    pub fn parse_instruction(&self, line: usize, s: &str) -> Result<Instruction, ParseError> {
+        let mut iter = s.split_whitespace();
+        let code = iter.next().unwrap_or_default();
+        let op = self.get(code).ok_or_else(|| ParseError::UnknownOpcode {
+            line,
+            opcode: code.to_string(),
+        })?;
+        let operand = iter.next().ok_or(ParseError::MissingOperand(line))?;
+        let arg = operand
+            .parse::<i32>()
+            .map_err(|_| ParseError::InvalidOperand {
+                line,
+                operand: operand.to_string(),
+            })?;
+        Ok(Instruction { op, arg })
+    }
+
+    pub fn parse_program<T: AsRef<str>>(&self, source: T) -> Result<Program, ParseError> {
+        let instructions = source
             .as_ref()
-            .split("\n")
-            .map(|line| Instruction::from(line))
-            .collect::<Vec<_>>();
+            .lines()
+            .enumerate()
+            .map(|(i, line)| self.parse_instruction(i + 1, line))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Program::new(instructions))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Instruction {
+    pub op: Opcode,
+    pub arg: i32,
+}
+
+impl Instruction {
+    pub fn new(op: Opcode, arg: i32) -> Self {
+        Instruction { op, arg }
+    }
+
+    // Every ip this instruction may hand over to when run at `ip`
+    pub fn successors(&self, ip: usize) -> Vec<isize> {
+        let next = ip as isize + 1;
+        let target = ip as isize + self.arg as isize;
+        match self.op.flow {
+            Flow::Next => vec![next],
+            Flow::Jump => vec![target],
+            Flow::Branch if target == next => vec![next],
+            Flow::Branch => vec![next, target],
+        }
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = ParseError;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        InstructionSet::default().parse_instruction(1, s)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:+}", self.op.name, self.arg)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FinishCondition {
+    // The ip landed right after the last instruction
+    Correctly,
+    InfiniteLoop,
+    // The ip landed anywhere else outside the program
+    Overflow,
+}
+
+// One bit per line
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct BitSet(Vec<u64>);
+
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        BitSet(vec![0; len.div_ceil(64)])
+    }
+
+    // Whether `i` was not already in the set
+    pub fn insert(&mut self, i: usize) -> bool {
+        let (word, bit) = (i / 64, 1 << (i % 64));
+        if word >= self.0.len() {
+            self.0.resize(word + 1, 0);
+        }
+        let new = self.0[word] & bit == 0;
+        self.0[word] |= bit;
+        new
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.0.get(i / 64).is_some_and(|w| w & (1 << (i % 64)) != 0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    // Signed so jumps before the first line can be reported
+    ip: isize,
+    acc: i32,
+    output: Vec<i32>,
+    lines_executed: BitSet,
+    // Every executed step, only kept once `record_trace` asks for it
+    trace: Option<Vec<TraceStep>>,
+}
+
+impl Program {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        let lines_executed = BitSet::new(instructions.len());
         Program {
             instructions,
             ip: 0,
             acc: 0,
-            lines_executed: Vec::new(),
+            output: vec![],
+            lines_executed,
+            trace: None,
         }
     }
-}
 
-impl<T: AsRef<str>> From<T> for Instruction {
-    fn from(line: T) -> Self {
-        let mut iter = line.as_ref().split(" ");
-        let code = iter.next().unwrap();
+    pub fn record_trace(mut self) -> Self {
+        self.trace = Some(vec![]);
+        self
+    }
+
+    pub fn trace(&self) -> Option<&[TraceStep]> {
+        self.trace.as_deref()
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn ip(&self) -> isize {
+        self.ip
+    }
+
+    pub fn acc(&self) -> i32 {
+        self.acc
+    }
 
-        match code {
-            "acc" => {
-                let val = iter.next().unwrap().parse::<i32>().unwrap();
-                Instruction::Acc(val)
+    pub fn output(&self) -> &[i32] {
+        &self.output
+    }
+
+    // Set once the ip has left the program
+    pub fn halted(&self) -> Option<FinishCondition> {
+        match self.ip {
+            ip if ip == self.instructions.len() as isize => Some(FinishCondition::Correctly),
+            ip if ip < 0 || ip > self.instructions.len() as isize => {
+                Some(FinishCondition::Overflow)
             }
-            "jmp" => {
-                let val = iter.next().unwrap().parse::<i32>().unwrap();
-                Instruction::Jmp(val)
+            _ => None,
+        }
+    }
+
+    // Executes the instruction at the ip, if any
+    pub fn step(&mut self) -> Option<FinishCondition> {
+        if let Some(condition) = self.halted() {
+            return Some(condition);
+        }
+        let (ip, acc_before) = (self.ip, self.acc);
+        let Instruction { op, arg } = self.instructions[ip as usize];
+        self.ip += match (op.exec)(self, arg) {
+            Goto::Next => 1,
+            Goto::Offset(offset) => offset as isize,
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceStep {
+                step: trace.len() + 1,
+                ip: ip as usize,
+                opcode: op.name,
+                operand: arg,
+                acc_before,
+                acc_after: self.acc,
+            });
+        }
+        self.halted()
+    }
+
+    // Runs until the program halts or is about to run a line a second time
+    pub fn run_safe(&mut self) -> (i32, FinishCondition) {
+        loop {
+            if let Some(condition) = self.halted() {
+                return (self.acc, condition);
             }
-            "nop" => {
-                let val = iter.next().unwrap().parse::<i32>().unwrap();
-                Instruction::Nop(val)
+            if !self.lines_executed.insert(self.ip as usize) {
+                return (self.acc, FinishCondition::InfiniteLoop);
             }
-            _ => panic!("Invalid input"),
+            self.step();
         }
     }
 }
 
+// The day's input format, one instruction per line
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+// Panics on anything the default instruction set can't parse, see
+// `InstructionSet::parse_program` for the fallible version
+impl<T: AsRef<str>> From<T> for Program {
+    fn from(lines: T) -> Self {
+        InstructionSet::default()
+            .parse_program(lines)
+            .unwrap_or_else(|e| panic!("Invalid program: {}", e))
+    }
+}
+
 fn main() {
     let lines = read_lines("src/inputs/day_08.txt")
         .unwrap()
-        .flatten()
+        .map_while(Result::ok)
         .collect::<Vec<_>>();
 
-    let mut program = Program::from(lines.join("\n"));
-    println!("Part 1: {:?}", program.run_safe());
-
-    let program = Program::from(lines.join("\n"));
-    let result = program
-        .instructions
-        .iter()
-        .enumerate()
-        .filter(|(_, v)| match v {
-            Instruction::Acc(_) => false,
-            _ => true,
-        })
-        .map(|(i, _)| {
-            let mut p = program.clone();
-            p.replace_instruction(i);
-            p.run_safe()
-        })
-        .filter(|(_, condition)| *condition != FinishCondition::InfiniteLoop)
-        .collect::<Vec<_>>();
-    println!("Part 2: {:?}", result);
+    // `day_08 asm FILE` assembles a labeled program into the input format
+    if std::env::args().nth(1).as_deref() == Some("asm") {
+        let path = std::env::args().nth(2).expect("Missing assembly file");
+        let source = std::fs::read_to_string(path).unwrap();
+        match asm::assemble(&InstructionSet::extended(), &source) {
+            Ok(program) => print!("{}", program),
+            Err(errors) => errors.iter().for_each(|e| eprintln!("{}", e)),
+        }
+        return;
+    }
+
+    let program = match InstructionSet::default().parse_program(lines.join("\n")) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    // `day_08 debug` reads debugger commands from stdin
+    if std::env::args().nth(1).as_deref() == Some("debug") {
+        let stdin = std::io::stdin();
+        debugger::session(&mut Debugger::new(program), stdin.lock(), std::io::stdout()).unwrap();
+        return;
+    }
+
+    // `day_08 disasm` prints the input with labels instead of offsets
+    if std::env::args().nth(1).as_deref() == Some("disasm") {
+        print!("{}", asm::disassemble(&program));
+        return;
+    }
+
+    // `day_08 trace jsonl|csv [--summary]` exports every step of part 1, or
+    // how many times each line ran
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(|a| a.as_str()) == Some("trace") {
+        let format = args
+            .get(2)
+            .map_or(Ok(Format::Csv), |f| f.parse::<Format>())
+            .unwrap();
+        let mut traced = program.record_trace();
+        traced.run_safe();
+        let stdout = std::io::stdout();
+        if args.iter().any(|a| a == "--summary") {
+            format.write(stdout.lock(), &trace::summary(&traced))
+        } else {
+            format.write(stdout.lock(), traced.trace().unwrap_or_default())
+        }
+        .unwrap();
+        return;
+    }
+
+    // `day_08 cfg [--dot]` lists static analysis findings or draws the graph
+    if std::env::args().nth(1).as_deref() == Some("cfg") {
+        let cfg = cfg::Cfg::new(&program);
+        if std::env::args().any(|a| a == "--dot") {
+            print!("{}", cfg.to_dot());
+        } else {
+            println!("{} blocks", cfg.blocks().len());
+            cfg.findings().iter().for_each(|f| println!("{}", f));
+        }
+        return;
+    }
+
+    // `day_08 repair [OPCODE MIN MAX]` looks for a fix that gives `OPCODE`
+    // instructions another operand instead of swapping `jmp` and `nop`
+    if args.get(1).map(|a| a.as_str()) == Some("repair") {
+        let fix = match &args[2..] {
+            [op, min, max] => {
+                let op = InstructionSet::default().get(op).expect("Unknown opcode");
+                let (min, max) = (min.parse().unwrap(), max.parse().unwrap());
+                repair::repair(
+                    &program,
+                    &repair::Operand {
+                        op,
+                        values: min..=max,
+                    },
+                )
+            }
+            _ => repair::repair(&program, &repair::Flip),
+        };
+        match fix {
+            Some(fix) => println!("{}", fix),
+            None => println!("No repair found"),
+        }
+        return;
+    }
+
+    println!("Part 1: {:?}", program.clone().run_safe());
+
+    match repair::repair(&program, &repair::Flip) {
+        Some(fix) => println!("Part 2: {:?}", fix.acc),
+        None => println!("Part 2: no single flip makes the program terminate"),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    static EXAMPLE: [&str; 9] = [
+        "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4", "acc +6",
+    ];
+
     #[test]
     fn example_p1() {
-        let input = vec![
-            "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4",
-            "acc +6",
-        ]
-        .join("\n");
+        let input = EXAMPLE.join("\n");
 
         let mut program = Program::from(input);
         assert_eq!(program.run_safe(), (5, FinishCondition::InfiniteLoop));
     }
+
+    #[test]
+    fn runs_the_last_instruction() {
+        let mut program = Program::from("nop +0\nacc +1\nacc +6");
+        assert_eq!(program.run_safe(), (7, FinishCondition::Correctly));
+        assert_eq!(program.ip(), 3);
+
+        let mut program = Program::from("acc +1\njmp +3\nacc +6");
+        assert_eq!(program.run_safe(), (1, FinishCondition::Overflow));
+
+        let mut program = Program::from("acc +1\njmp -2");
+        assert_eq!(program.run_safe(), (1, FinishCondition::Overflow));
+        assert_eq!(program.ip(), -1);
+    }
+
+    #[test]
+    fn long_programs_do_not_overflow_the_stack() {
+        let source = vec!["acc +1"; 200_000].join("\n");
+        let mut program = Program::from(source);
+        assert_eq!(program.run_safe(), (200_000, FinishCondition::Correctly));
+    }
+
+    #[test]
+    fn extended_instruction_set() {
+        let source = "acc +3\nmul -2\nout +0\njz +3\nacc +6\njz -3\nout +0";
+        assert!(InstructionSet::default().parse_program(source).is_err());
+
+        let mut program = InstructionSet::extended().parse_program(source).unwrap();
+        // `out` runs twice, which `run_safe` would take for a loop
+        while program.step().is_none() {}
+        assert_eq!(program.halted(), Some(FinishCondition::Correctly));
+        assert_eq!(program.output(), &[-6, 0, 0]);
+    }
+
+    #[test]
+    fn registered_opcodes_replace_builtins() {
+        const HALT: Opcode = Opcode {
+            name: "nop",
+            flow: Flow::Jump,
+            exec: |p, _| Goto::Offset(p.instructions.len() as i32),
+        };
+        let set = InstructionSet::default().register(HALT);
+        let mut program = set.parse_program("acc +1\nnop +0\nacc +1").unwrap();
+        assert_eq!(program.run_safe(), (1, FinishCondition::Overflow));
+    }
+
+    #[test]
+    fn invalid_programs() {
+        let set = InstructionSet::default();
+        assert_eq!(
+            set.parse_program("nop +0\nhcf +1"),
+            Err(ParseError::UnknownOpcode {
+                line: 2,
+                opcode: "hcf".to_string()
+            })
+        );
+        assert_eq!(set.parse_program("acc"), Err(ParseError::MissingOperand(1)));
+        assert_eq!(
+            "jmp four".parse::<Instruction>(),
+            Err(ParseError::InvalidOperand {
+                line: 1,
+                operand: "four".to_string()
+            })
+        );
+        assert_eq!("jmp -4".parse(), Ok(Instruction::new(JMP, -4)));
+    }
+
+    #[test]
+    fn debugger_breakpoints_and_reverse_steps() {
+        use debugger::{Breakpoint, Cmp, Stop, Watch};
+
+        let input = EXAMPLE.join("\n");
+        let mut debugger = Debugger::new(Program::from(input));
+
+        debugger.add_breakpoint(Breakpoint::Line(4));
+        assert_eq!(debugger.cont(), Stop::Breakpoint(Breakpoint::Line(4)));
+        assert_eq!(debugger.current(), "ip 4  acc 5  | jmp -3");
+        assert_eq!(
+            debugger.history().collect::<Vec<_>>(),
+            vec![0, 1, 2, 6, 7, 3]
+        );
+
+        assert_eq!(debugger.step_back(), Stop::Stepped);
+        assert_eq!(debugger.current(), "ip 3  acc 2  | acc +3");
+        assert!(debugger.remove_breakpoint(Breakpoint::Line(4)));
+        assert_eq!(debugger.cont(), Stop::InfiniteLoop);
+
+        while debugger.step_back() == Stop::Stepped {}
+        assert_eq!(debugger.current(), "ip 0  acc 0  | nop +0");
+        debugger.add_breakpoint(Breakpoint::Acc(Cmp::Gt, 1));
+        assert_eq!(
+            debugger.cont(),
+            Stop::Breakpoint(Breakpoint::Acc(Cmp::Gt, 1))
+        );
+        assert_eq!(debugger.program().acc(), 2);
+
+        debugger.remove_breakpoint(Breakpoint::Acc(Cmp::Gt, 1));
+        debugger.watch(Watch::Acc);
+        assert_eq!(
+            debugger.cont(),
+            Stop::Watch {
+                watch: Watch::Acc,
+                old: 2,
+                new: 5
+            }
+        );
+    }
+
+    #[test]
+    fn debugger_session() {
+        let mut debugger = Debugger::new(Program::from("acc +1\nacc +2\njmp +1"));
+        let commands = "step 2\nback\nbreak acc == 3\nc\nfly\nc\nhistory\nq\np\n";
+        let mut out = vec![];
+        debugger::session(&mut debugger, commands.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                "ip 0  acc 0  | acc +1",
+                "ip 2  acc 3  | jmp +1",
+                "ip 1  acc 1  | acc +2",
+                "breakpoint set: acc == 3",
+                "breakpoint: acc == 3",
+                "ip 2  acc 3  | jmp +1",
+                "Unknown command \"fly\"",
+                "halted: Correctly",
+                "ip 3  acc 3  | <halted>",
+                "0 1 2",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn example_p2() {
+        let input = EXAMPLE.join("\n");
+        let program = Program::from(input);
+
+        let fix = repair::repair(&program, &repair::Flip).unwrap();
+        assert_eq!(fix.to_string(), "line 7: jmp -4 -> nop -4 (acc 8)");
+
+        let terminating = repair::terminating(&program);
+        let lines = (0..=9)
+            .filter(|l| terminating.contains(*l))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![8, 9]);
+    }
+
+    #[test]
+    fn repair_with_other_mutations() {
+        let program = Program::from("acc +1\njmp -1\nacc +2");
+
+        let operand = repair::Operand {
+            op: JMP,
+            values: -2..=2,
+        };
+        let fix = repair::repair(&program, &operand).unwrap();
+        assert_eq!(
+            (fix.line, fix.to, fix.acc),
+            (1, Instruction::new(JMP, 1), 3)
+        );
+
+        let operand = repair::Operand {
+            op: ACC,
+            values: -2..=2,
+        };
+        assert_eq!(repair::repair(&program, &operand), None);
+        assert_eq!(repair::repair(&program, &repair::Flip).unwrap().acc, 3);
+    }
+
+    #[test]
+    fn conditional_jumps_terminate_only_if_both_ways_do() {
+        let set = InstructionSet::extended();
+        let program = set.parse_program("jz +2\njmp +0\nacc +1").unwrap();
+        let terminating = repair::terminating(&program);
+        assert!(!terminating.contains(0));
+        assert!(terminating.contains(2));
+
+        let program = set.parse_program("jz +2\nnop +0\nacc +1").unwrap();
+        assert!(repair::terminating(&program).contains(0));
+    }
+
+    #[test]
+    fn control_flow_graph() {
+        use cfg::{Block, Cfg, Target};
+
+        let input = EXAMPLE.join("\n");
+        let cfg = Cfg::new(&Program::from(input));
+
+        assert_eq!(
+            cfg.blocks()
+                .iter()
+                .map(|b| b.lines.clone())
+                .collect::<Vec<_>>(),
+            vec![0..1, 1..3, 3..5, 5..6, 6..8, 8..9]
+        );
+        assert_eq!(
+            cfg.blocks()[2],
+            Block {
+                lines: 3..5,
+                successors: vec![Target::Block(1)],
+                predecessors: vec![4],
+            }
+        );
+        assert_eq!(cfg.blocks()[5].successors, vec![Target::Exit]);
+        assert_eq!(
+            cfg.findings()
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "lines 0..1: never terminate",
+                "lines 1..3: never terminate",
+                "lines 3..5: never terminate",
+                "lines 5..6: unreachable",
+                "lines 6..8: never terminate",
+                "lines 8..9: unreachable",
+            ]
+        );
+    }
+
+    #[test]
+    fn out_of_bounds_jumps_and_dot() {
+        let set = InstructionSet::extended();
+        let cfg = cfg::Cfg::new(&set.parse_program("jz +3\njmp -2\nacc +1").unwrap());
+
+        let findings = cfg.findings();
+        assert_eq!(
+            findings,
+            vec![
+                cfg::Finding::OutOfBounds {
+                    line: 1,
+                    target: -1
+                },
+                cfg::Finding::Unreachable(2..3),
+            ]
+        );
+        assert_eq!(
+            cfg.to_dot(),
+            [
+                "digraph cfg {",
+                "    node [shape=box fontname=monospace];",
+                "    b0 [label=\"0: jz +3\\l\"];",
+                "    b1 [label=\"1: jmp -2\\l\"];",
+                "    b2 [label=\"2: acc +1\\l\" style=dashed];",
+                "    exit [shape=doublecircle];",
+                "    b0 -> b1;",
+                "    b0 -> exit;",
+                "    \"out -1\" [shape=octagon];",
+                "    b1 -> \"out -1\";",
+                "    b2 -> exit;",
+                "}",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn assemble_labels() {
+        let source = [
+            "; part 1 example, labeled",
+            "start: nop +0",
+            "loop:",
+            "    acc +1",
+            "    jmp skip   ; forward",
+            "back: acc +3",
+            "    jmp loop",
+            "    acc -99",
+            "skip: acc +1",
+            "    jmp back",
+            "    acc +6",
+        ]
+        .join("\n");
+        let program = asm::assemble(&InstructionSet::default(), &source).unwrap();
+        let example = EXAMPLE.join("\n");
+
+        assert_eq!(program, Program::from(&example));
+        assert_eq!(program.to_string(), example + "\n");
+    }
+
+    #[test]
+    fn assembler_errors() {
+        let source = "a: nop +0\njmp b\na: acc +1\n9x: jmp a\nhcf +0";
+        let errors = asm::assemble(&InstructionSet::default(), source).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec![
+                "line 2: undefined label \"b\"",
+                "line 3: label \"a\" already defined on line 1",
+                "line 4: invalid label \"9x\"",
+                "line 5: unknown opcode \"hcf\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_round_trip() {
+        let set = InstructionSet::extended();
+        let program = set
+            .parse_program("nop +0\nacc +1\njmp +4\njz -3\njmp -9\nacc -99\nacc +1\njmp -4\njmp +1")
+            .unwrap();
+        let source = asm::disassemble(&program);
+
+        assert_eq!(
+            source,
+            [
+                "L0:",
+                "    nop +0",
+                "    acc +1",
+                "    jmp L2",
+                "L1:",
+                "    jz L0",
+                "    jmp -9",
+                "    acc -99",
+                "L2:",
+                "    acc +1",
+                "    jmp L1",
+                "    jmp L3",
+                "L3:",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(asm::assemble(&set, &source), Ok(program.clone()));
+        assert_eq!(set.parse_program(program.to_string()), Ok(program));
+    }
+
+    #[test]
+    fn trace_export() {
+        let mut program = Program::from("acc +2\njmp +2\nacc -1\nacc +3").record_trace();
+        assert_eq!(program.run_safe(), (5, FinishCondition::Correctly));
+        assert_eq!(
+            program.trace().unwrap()[1],
+            TraceStep {
+                step: 2,
+                ip: 1,
+                opcode: "jmp",
+                operand: 2,
+                acc_before: 2,
+                acc_after: 2,
+            }
+        );
+
+        let mut out = vec![];
+        Format::Csv
+            .write(&mut out, program.trace().unwrap())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                "step,ip,opcode,operand,acc_before,acc_after",
+                "1,0,acc,2,0,2",
+                "2,1,jmp,2,2,2",
+                "3,3,acc,3,2,5",
+                "",
+            ]
+            .join("\n")
+        );
+
+        let mut out = vec![];
+        Format::JsonLines
+            .write(&mut out, &trace::summary(&program)[1..3])
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                r#"{"line":1,"opcode":"jmp","operand":2,"count":1}"#,
+                r#"{"line":2,"opcode":"acc","operand":-1,"count":0}"#,
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn trace_counts_loops_and_reverse_steps() {
+        let input = EXAMPLE.join("\n");
+        let mut debugger = Debugger::new(Program::from(input).record_trace());
+        while debugger.step() == debugger::Stop::Stepped
+            && debugger.program().trace().unwrap().len() < 10
+        {}
+        debugger.step_back();
+
+        let counts = trace::summary(debugger.program())
+            .iter()
+            .map(|c| c.count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![1, 2, 2, 1, 1, 0, 1, 1, 0]);
+    }
 }
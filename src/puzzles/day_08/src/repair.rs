@@ -0,0 +1,126 @@
+use crate::{BitSet, FinishCondition, Instruction, Opcode, Program, JMP, NOP};
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+// Ways a single instruction may be rewritten
+pub trait Mutation {
+    fn candidates(&self, instruction: Instruction) -> Vec<Instruction>;
+}
+
+// Swaps `jmp` and `nop`, keeping the operand
+pub struct Flip;
+
+impl Mutation for Flip {
+    fn candidates(&self, instruction: Instruction) -> Vec<Instruction> {
+        match instruction.op {
+            op if op == JMP => vec![Instruction::new(NOP, instruction.arg)],
+            op if op == NOP => vec![Instruction::new(JMP, instruction.arg)],
+            _ => vec![],
+        }
+    }
+}
+
+// Gives instructions of one opcode any other operand in `values`
+pub struct Operand {
+    pub op: Opcode,
+    pub values: RangeInclusive<i32>,
+}
+
+impl Mutation for Operand {
+    fn candidates(&self, instruction: Instruction) -> Vec<Instruction> {
+        if instruction.op != self.op {
+            return vec![];
+        }
+        self.values
+            .clone()
+            .filter(|v| *v != instruction.arg)
+            .map(|v| Instruction::new(self.op, v))
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Repair {
+    pub line: usize,
+    pub from: Instruction,
+    pub to: Instruction,
+    // Accumulator once the repaired program terminates
+    pub acc: i32,
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {} -> {} (acc {})",
+            self.line, self.from, self.to, self.acc
+        )
+    }
+}
+
+// Lines from which the program is bound to end right after its last
+// instruction, plus that end address itself. Built backwards from the end:
+// a line joins once every ip it may hand over to has joined.
+pub fn terminating(program: &Program) -> BitSet {
+    let len = program.instructions.len();
+    let mut pending = vec![0; len];
+    let mut predecessors = vec![vec![]; len + 1];
+    for (line, instruction) in program.instructions.iter().enumerate() {
+        let successors = instruction.successors(line);
+        if successors.iter().all(|s| (0..=len as isize).contains(s)) {
+            pending[line] = successors.len();
+            for s in successors {
+                predecessors[s as usize].push(line);
+            }
+        }
+    }
+
+    let mut set = BitSet::new(len + 1);
+    set.insert(len);
+    let mut queue = VecDeque::from(vec![len]);
+    while let Some(line) = queue.pop_front() {
+        for p in &predecessors[line] {
+            pending[*p] -= 1;
+            if pending[*p] == 0 && set.insert(*p) {
+                queue.push_back(*p);
+            }
+        }
+    }
+    set
+}
+
+// The first line on the looping path whose rewrite lands on a terminating
+// line. Everything the original run reaches loops, so that path can't be
+// taken again once execution leaves it; the search is linear in the program
+// size times the candidates per line.
+pub fn repair<M: Mutation>(program: &Program, mutation: &M) -> Option<Repair> {
+    let terminating = terminating(program);
+    let mut run = Program::new(program.instructions.clone());
+    let mut visited = BitSet::new(program.instructions.len());
+
+    while run.halted().is_none() && visited.insert(run.ip as usize) {
+        let line = run.ip as usize;
+        let from = run.instructions[line];
+        let fix = mutation.candidates(from).into_iter().find(|to| {
+            to.successors(line)
+                .iter()
+                .all(|s| *s >= 0 && terminating.contains(*s as usize))
+        });
+        if let Some(to) = fix {
+            let mut repaired = Program::new(program.instructions.clone());
+            repaired.instructions[line] = to;
+            return match repaired.run_safe() {
+                (acc, FinishCondition::Correctly) => Some(Repair {
+                    line,
+                    from,
+                    to,
+                    acc,
+                }),
+                _ => None,
+            };
+        }
+        run.step();
+    }
+    None
+}
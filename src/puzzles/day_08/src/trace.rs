@@ -0,0 +1,41 @@
+use crate::Program;
+use serde::Serialize;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct TraceStep {
+    // 1-based
+    pub step: usize,
+    pub ip: usize,
+    pub opcode: &'static str,
+    pub operand: i32,
+    pub acc_before: i32,
+    pub acc_after: i32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct LineCount {
+    pub line: usize,
+    pub opcode: &'static str,
+    pub operand: i32,
+    pub count: usize,
+}
+
+// How many times every line ran in the recorded trace, unrun lines included
+pub fn summary(program: &Program) -> Vec<LineCount> {
+    let mut counts = vec![0; program.instructions.len()];
+    for step in program.trace().unwrap_or_default() {
+        counts[step.ip] += 1;
+    }
+    program
+        .instructions
+        .iter()
+        .zip(counts)
+        .enumerate()
+        .map(|(line, (instruction, count))| LineCount {
+            line,
+            opcode: instruction.op.name,
+            operand: instruction.arg,
+            count,
+        })
+        .collect()
+}
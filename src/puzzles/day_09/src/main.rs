@@ -1,5 +1,106 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, Write};
 use utils::read_lines;
 
+// The last `len` values pushed, with a count per value for O(1) lookups
+pub struct Window {
+    len: usize,
+    values: VecDeque<usize>,
+    counts: HashMap<usize, usize>,
+}
+
+impl Window {
+    pub fn new(len: usize) -> Self {
+        Window {
+            len,
+            values: VecDeque::with_capacity(len + 1),
+            counts: HashMap::with_capacity(len),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.values.len() == self.len
+    }
+
+    // Drops the oldest value once the window is full
+    pub fn push(&mut self, v: usize) {
+        self.values.push_back(v);
+        *self.counts.entry(v).or_insert(0) += 1;
+        if self.values.len() > self.len {
+            let old = self.values.pop_front().unwrap();
+            match self.counts.get_mut(&old) {
+                Some(1) => {
+                    self.counts.remove(&old);
+                }
+                Some(count) => *count -= 1,
+                None => unreachable!("Every value in the window is counted"),
+            }
+        }
+    }
+
+    // Whether two different values in the window add up to `target`
+    pub fn has_pair_sum(&self, target: usize) -> bool {
+        self.counts
+            .keys()
+            .any(|x| *x < target && target - x != *x && self.counts.contains_key(&(target - x)))
+    }
+}
+
+// Checks numbers one at a time against the ones right before them, so the
+// whole sequence never has to be in memory
+pub struct Validator {
+    window: Window,
+    index: usize,
+}
+
+impl Validator {
+    pub fn new(preamble: usize) -> Self {
+        Validator {
+            window: Window::new(preamble),
+            index: 0,
+        }
+    }
+
+    // (index, value) when `v` isn't the sum of two numbers in the preamble
+    pub fn push(&mut self, v: usize) -> Option<(usize, usize)> {
+        let invalid = self.window.is_full() && !self.window.has_pair_sum(v);
+        self.window.push(v);
+        self.index += 1;
+        if invalid {
+            Some((self.index - 1, v))
+        } else {
+            None
+        }
+    }
+}
+
+// Writes `index: value` for every invalid number as soon as it's read
+pub fn stream<R: BufRead, W: Write>(input: R, preamble: usize, mut out: W) -> io::Result<()> {
+    let mut validator = Validator::new(preamble);
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        let v = line.trim().parse::<usize>().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: not a number {:?}", i + 1, line),
+            )
+        })?;
+        if let Some((index, v)) = validator.push(v) {
+            writeln!(out, "{}: {}", index, v)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// Which contiguous range to use when several add up to the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pick {
+    First,
+    Shortest,
+    Longest,
+}
+
 struct Seq {
     numbers: Vec<usize>,
     buff_len: usize,
@@ -36,90 +137,123 @@ impl Seq {
 
     pub fn from_str<T: AsRef<str>>(str: T, len: usize) -> Self {
         let mut seq = Seq::new(len);
-        str.as_ref().split("\n").for_each(|line| {
+        str.as_ref().split('\n').for_each(|line| {
             seq.push(line.parse::<usize>().unwrap());
         });
         seq
     }
 
+    // (index, value) of every number that isn't the sum of two of the
+    // `buff_len` before it, found lazily
+    pub fn invalid(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut validator = Validator::new(self.buff_len);
+        self.numbers.iter().filter_map(move |v| validator.push(*v))
+    }
+
+    // Stops at the first invalid number
     pub fn validate(&self) -> ValidationResult {
-        let total = self.numbers.len();
-        if total < self.buff_len {
-            ValidationResult::TooShort
-        } else {
-            (self.buff_len..total).fold(ValidationResult::Valid, |acc, i| {
-                if acc != ValidationResult::Valid {
-                    acc
-                } else {
-                    let nums = self.numbers[i - self.buff_len..i].to_vec();
-                    let target = self.numbers[i];
-                    let is_valid = nums.iter().any(|x| {
-                        let mut result = false;
-                        for y in &nums {
-                            if *x != *y && x + y == target {
-                                result = true
-                            }
-                        }
-                        result
-                    });
-
-                    if is_valid {
-                        ValidationResult::Valid
-                    } else {
-                        ValidationResult::Invalid(self.numbers[i])
-                    }
-                }
-            })
+        if self.numbers.len() < self.buff_len {
+            return ValidationResult::TooShort;
+        }
+        match self.invalid().next() {
+            Some((_, v)) => ValidationResult::Invalid(v),
+            None => ValidationResult::Valid,
         }
     }
 
-    pub fn find_sum_set(&self, target: usize) -> Option<Vec<usize>> {
-        let mut result = None;
-        for n in 0..self.numbers.len() {
-            if result.is_none() {
-                let (sum, set) = self.numbers[n..self.numbers.len()].into_iter().fold(
-                    (0, vec![]),
-                    |(acc, mut set), v| {
-                        if acc >= target {
-                            (acc, set)
-                        } else {
-                            set.push(*v);
-                            (acc + *v, set)
-                        }
-                    },
-                );
-                if sum == target && set.len() > 1 {
-                    result = Some(set)
-                }
+    pub fn validate_all(&self) -> Vec<(usize, usize)> {
+        self.invalid().collect()
+    }
+
+    // Inclusive (start, end) of every run of at least two numbers adding up
+    // to `target`, ordered by start. A run ending at `j` starts right after
+    // any earlier prefix that is exactly `target` less than the prefix up to
+    // `j`; numbers are never negative, so looking prefixes up by value
+    // visits each matching run once.
+    pub fn sum_ranges(&self, target: usize) -> Vec<(usize, usize)> {
+        let mut prefixes: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut ranges = vec![];
+        let mut sum = 0;
+        for (j, v) in self.numbers.iter().enumerate() {
+            // Prefix sums over numbers[..j - 1] become starts once `j`
+            // is far enough for a two-number run
+            if j > 0 {
+                let before = sum - self.numbers[j - 1];
+                prefixes.entry(before).or_default().push(j - 1);
+            }
+            sum += v;
+            if let Some(starts) = sum.checked_sub(target).and_then(|p| prefixes.get(&p)) {
+                ranges.extend(starts.iter().map(|start| (*start, j)));
             }
         }
-        result
+        ranges.sort_unstable();
+        ranges
     }
 
-    pub fn find_sum_set_result(&self, target: usize) -> Option<usize> {
-        self.find_sum_set(target).and_then(|mut vec| {
-            if vec.len() < 2 {
-                None
-            } else {
-                vec.sort();
-                Some(vec[0] + vec[vec.len() - 1])
-            }
-        })
+    pub fn sum_range(&self, target: usize, pick: Pick) -> Option<(usize, usize)> {
+        let ranges = self.sum_ranges(target);
+        let len = |(start, end): &(usize, usize)| end - start;
+        match pick {
+            Pick::First => ranges.first().copied(),
+            Pick::Shortest => ranges.iter().min_by_key(|r| len(r)).copied(),
+            Pick::Longest => ranges
+                .iter()
+                .max_by_key(|r| (len(r), std::cmp::Reverse(r.0)))
+                .copied(),
+        }
+    }
+
+    pub fn find_sum_set(&self, target: usize, pick: Pick) -> Option<Vec<usize>> {
+        self.sum_range(target, pick)
+            .map(|(start, end)| self.numbers[start..=end].to_vec())
+    }
+
+    pub fn find_sum_set_result(&self, target: usize, pick: Pick) -> Option<usize> {
+        self.find_sum_set(target, pick)
+            .map(|set| set.iter().min().unwrap() + set.iter().max().unwrap())
     }
 }
 
 fn main() {
+    // `day_09 stream [PREAMBLE]` validates numbers from stdin as they arrive
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(|a| a.as_str()) == Some("stream") {
+        let preamble = args.get(2).map_or(25, |p| p.parse().unwrap());
+        let stdin = io::stdin();
+        if let Err(e) = stream(stdin.lock(), preamble, io::stdout()) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+
     let lines = read_lines("src/inputs/day_09.txt")
         .unwrap()
-        .flatten()
+        .map_while(Result::ok)
         .collect::<Vec<_>>();
 
     let seq = Seq::from_str(lines.join("\n"), 25);
+
+    // `day_09 --all` lists every invalid number instead of stopping at the first
+    if args.iter().any(|a| a == "--all") {
+        for (i, v) in seq.validate_all() {
+            println!("{}: {}", i, v);
+        }
+        return;
+    }
+
     let result1 = seq.validate();
     println!("Part 1: {:?}", result1);
 
+    // `day_09 --shortest` or `--longest` choose among several matching ranges
+    let pick = if args.iter().any(|a| a == "--shortest") {
+        Pick::Shortest
+    } else if args.iter().any(|a| a == "--longest") {
+        Pick::Longest
+    } else {
+        Pick::First
+    };
     let invalid_value = ValidationResult::invalid_value(result1);
-    println!("Part 2: {:?}", seq.find_sum_set_result(invalid_value));
+    println!("Part 2: {:?}", seq.find_sum_set_result(invalid_value, pick));
 }
 
 #[cfg(test)]
@@ -147,6 +281,57 @@ mod test {
         .join("\n");
 
         let seq = Seq::from_str(input, 5);
-        assert_eq!(seq.find_sum_set_result(127), Some(62));
+        assert_eq!(seq.find_sum_set_result(127, Pick::First), Some(62));
+    }
+
+    #[test]
+    fn window_pair_sums() {
+        let mut window = Window::new(3);
+        [1, 2, 2, 5].iter().for_each(|v| window.push(*v));
+
+        assert!(window.is_full());
+        assert!(window.has_pair_sum(7));
+        assert!(!window.has_pair_sum(3));
+        // Both 2s are in the window but the pair must be two different values
+        assert!(!window.has_pair_sum(4));
+    }
+
+    #[test]
+    fn report_all_failures() {
+        let seq = Seq::from_str("1\n2\n3\n9\n4\n5\n20\n25", 2);
+
+        assert_eq!(seq.validate(), ValidationResult::Invalid(9));
+        assert_eq!(seq.validate_all(), vec![(3, 9), (4, 4), (5, 5), (6, 20)]);
+        assert_eq!(Seq::from_str("1", 2).validate(), ValidationResult::TooShort);
+    }
+
+    #[test]
+    fn stream_reports_as_it_reads() {
+        let input = "1\n2\n3\n9\n4\n5\n20\n25\n";
+        let mut out = vec![];
+        stream(input.as_bytes(), 2, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "3: 9\n4: 4\n5: 5\n6: 20\n");
+
+        let mut out = vec![];
+        let error = stream("1\n2\n3\n9\nfour\n".as_bytes(), 2, &mut out).unwrap_err();
+        assert_eq!(error.to_string(), "line 5: not a number \"four\"");
+        assert_eq!(String::from_utf8(out).unwrap(), "3: 9\n");
+    }
+
+    #[test]
+    fn all_sum_ranges() {
+        let seq = Seq::from_str("5\n1\n4\n0\n3\n2\n5\n5", 2);
+
+        assert_eq!(seq.sum_ranges(5), vec![(1, 2), (1, 3), (3, 5), (4, 5)]);
+        assert_eq!(seq.sum_range(10, Pick::First), Some((0, 2)));
+        assert_eq!(seq.sum_range(10, Pick::Shortest), Some((6, 7)));
+        assert_eq!(seq.sum_range(10, Pick::Longest), Some((1, 5)));
+        assert_eq!(
+            seq.find_sum_set(10, Pick::Longest),
+            Some(vec![1, 4, 0, 3, 2])
+        );
+        assert_eq!(seq.sum_range(100, Pick::First), None);
+        // A number on its own is never a range
+        assert_eq!(seq.sum_ranges(4), vec![(2, 3)]);
     }
 }
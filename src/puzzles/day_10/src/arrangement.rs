@@ -0,0 +1,153 @@
+use crate::{joltages, ways, Tolerance};
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::Zero;
+use rand::Rng;
+
+// Every joltage from the outlet to the device, with how many ways each can be
+// reached from the outlet and whether the device can be reached from it
+pub struct Chain {
+    tolerance: Tolerance,
+    joltages: Vec<i64>,
+    ways: Vec<BigUint>,
+    finishing: Vec<bool>,
+}
+
+impl Chain {
+    pub fn new(adapters: &[i64], tolerance: Tolerance) -> Self {
+        let joltages = joltages(adapters, tolerance);
+        let ways = ways(&joltages, tolerance);
+        let mut chain = Chain {
+            tolerance,
+            finishing: vec![false; joltages.len()],
+            joltages,
+            ways,
+        };
+        let last = chain.joltages.len() - 1;
+        chain.finishing[last] = true;
+        for i in (0..last).rev() {
+            let finishing = chain.next(i, i + 1).any(|j| chain.finishing[j]);
+            chain.finishing[i] = finishing;
+        }
+        chain
+    }
+
+    fn connects(&self, from: usize, to: usize) -> bool {
+        let diff = self.joltages[to] - self.joltages[from];
+        diff >= self.tolerance.min && diff <= self.tolerance.max
+    }
+
+    // Indices from `start` on that `from` can be plugged into
+    fn next(&self, from: usize, start: usize) -> impl Iterator<Item = usize> + '_ {
+        (start..self.joltages.len())
+            .take_while(move |to| self.joltages[*to] - self.joltages[from] <= self.tolerance.max)
+            .filter(move |to| self.connects(from, *to))
+    }
+
+    pub fn count(&self) -> &BigUint {
+        self.ways.last().unwrap()
+    }
+
+    // Every valid subset of adapters, ascending and in lexicographic order
+    pub fn iter(&self) -> Arrangements<'_> {
+        let stack = if self.count().is_zero() {
+            vec![]
+        } else {
+            vec![(0, 1)]
+        };
+        Arrangements { chain: self, stack }
+    }
+
+    // One arrangement picked uniformly at random. Walks back from the device
+    // choosing each predecessor with odds proportional to the ways to reach it.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<Vec<i64>> {
+        if self.count().is_zero() {
+            return None;
+        }
+        let mut adapters = vec![];
+        let mut to = self.joltages.len() - 1;
+        while to > 0 {
+            let mut pick = rng.gen_biguint_below(&self.ways[to]);
+            to = (0..to)
+                .rev()
+                .filter(|from| self.connects(*from, to))
+                .find(|from| {
+                    if pick < self.ways[*from] {
+                        return true;
+                    }
+                    pick -= &self.ways[*from];
+                    false
+                })
+                .unwrap();
+            if to > 0 {
+                adapters.push(self.joltages[to]);
+            }
+        }
+        adapters.reverse();
+        Some(adapters)
+    }
+
+    // Adapters found in every arrangement: usable ones that no connection on
+    // a path from the outlet to the device skips over. Empty when there are no
+    // arrangements at all.
+    pub fn mandatory(&self) -> Vec<i64> {
+        if self.count().is_zero() {
+            return vec![];
+        }
+        let last = self.joltages.len() - 1;
+        let mut skips = vec![0i64; self.joltages.len() + 1];
+        for from in (0..last).filter(|i| !self.ways[*i].is_zero()) {
+            for to in self.next(from, from + 2) {
+                if self.finishing[to] {
+                    skips[from + 1] += 1;
+                    skips[to] -= 1;
+                }
+            }
+        }
+        let mut open = 0;
+        let mut mandatory = vec![];
+        for (i, skip) in skips.iter().enumerate().take(last).skip(1) {
+            open += skip;
+            if open == 0 && !self.ways[i].is_zero() && self.finishing[i] {
+                mandatory.push(self.joltages[i]);
+            }
+        }
+        mandatory
+    }
+}
+
+// Depth-first walk over connections, smallest joltage first, that only steps
+// on joltages the device can be reached from, so it never backtracks out of a
+// dead end
+pub struct Arrangements<'a> {
+    chain: &'a Chain,
+    // (joltage index, next index to try from it)
+    stack: Vec<(usize, usize)>,
+}
+
+impl Iterator for Arrangements<'_> {
+    type Item = Vec<i64>;
+
+    fn next(&mut self) -> Option<Vec<i64>> {
+        let chain = self.chain;
+        let device = chain.joltages.len() - 1;
+        loop {
+            let (from, start) = *self.stack.last()?;
+            match chain.next(from, start).find(|to| chain.finishing[*to]) {
+                None => {
+                    self.stack.pop();
+                }
+                Some(to) => {
+                    self.stack.last_mut().unwrap().1 = to + 1;
+                    if to == device {
+                        let adapters = self.stack[1..]
+                            .iter()
+                            .map(|(i, _)| chain.joltages[*i])
+                            .collect();
+                        return Some(adapters);
+                    }
+                    self.stack.push((to, to + 1));
+                }
+            }
+        }
+    }
+}
@@ -1,92 +1,348 @@
-use std::{cmp::max, collections::HashMap};
+mod arrangement;
 
+use arrangement::Chain;
+use num_bigint::BigUint;
+use std::collections::BTreeMap;
+use std::fmt;
 use utils::read_lines;
 
-pub fn solve_p1(_adapters: &Vec<i64>) -> i64 {
-    let mut adapters = _adapters.clone();
-    let last_val = adapters[adapters.len() - 1];
-    adapters.push(last_val + 3);
-
-    let result = adapters
-        .iter()
-        .enumerate()
-        .map(|(i, v)| if i == 0 { *v } else { *v - adapters[i - 1] })
-        .fold(HashMap::new(), |mut acc, v| {
-            let counter = acc.entry(v).or_insert(0);
-            *counter += 1;
-            acc
-        });
-    result.get(&1).unwrap() * result.get(&3).unwrap()
+// Joltage differences an adapter can take on its input side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tolerance {
+    pub min: i64,
+    pub max: i64,
 }
 
-pub fn solve_p2(_adapters: &Vec<i64>) -> i64 {
-    let mut adapters = _adapters.clone();
-    adapters.insert(0, 0);
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance { min: 1, max: 3 }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChainError {
+    // Two consecutive joltages that can't be connected
+    Gap { from: i64, to: i64 },
+}
 
-    let mut valid_arrangements: Vec<i64> = vec![];
-    valid_arrangements.resize(adapters.len() - 1, 0);
-    valid_arrangements.insert(0, 1);
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::Gap { from, to } => write!(f, "can't connect {} to {}", from, to),
+        }
+    }
+}
 
-    for index in 1..adapters.len() {
-        for src_index in max(0, (index as i64) - 3) as usize..index {
-            if adapters[index] - adapters[src_index] <= 3 {
-                valid_arrangements[index] += valid_arrangements[src_index]
+// The outlet, every adapter in ascending order and the device, which is
+// rated `tolerance.max` above the highest adapter
+pub fn joltages(adapters: &[i64], tolerance: Tolerance) -> Vec<i64> {
+    let mut joltages = adapters.to_vec();
+    joltages.sort_unstable();
+    let device = joltages.last().copied().unwrap_or(0) + tolerance.max;
+    joltages.insert(0, 0);
+    joltages.push(device);
+    joltages
+}
+
+// How many times each difference shows up when chaining every adapter
+pub fn differences(
+    adapters: &[i64],
+    tolerance: Tolerance,
+) -> Result<BTreeMap<i64, usize>, ChainError> {
+    let joltages = joltages(adapters, tolerance);
+    let mut histogram = BTreeMap::new();
+    for pair in joltages.windows(2) {
+        let diff = pair[1] - pair[0];
+        if diff < tolerance.min || diff > tolerance.max {
+            return Err(ChainError::Gap {
+                from: pair[0],
+                to: pair[1],
+            });
+        }
+        *histogram.entry(diff).or_insert(0) += 1;
+    }
+    Ok(histogram)
+}
+
+// Ways to get from the outlet to each joltage in `joltages`
+pub fn ways(joltages: &[i64], tolerance: Tolerance) -> Vec<BigUint> {
+    let mut ways = vec![BigUint::default(); joltages.len()];
+    ways[0] = BigUint::from(1u32);
+    for j in 1..joltages.len() {
+        let mut total = BigUint::default();
+        for i in (0..j).rev() {
+            let diff = joltages[j] - joltages[i];
+            if diff > tolerance.max {
+                break;
+            }
+            if diff >= tolerance.min {
+                total += &ways[i];
             }
         }
+        ways[j] = total;
     }
+    ways
+}
 
-    valid_arrangements[valid_arrangements.len() - 1]
+// Ways to get from the outlet to the device, picking any subset of adapters
+pub fn arrangements(adapters: &[i64], tolerance: Tolerance) -> BigUint {
+    ways(&joltages(adapters, tolerance), tolerance)
+        .pop()
+        .unwrap()
+}
+
+pub fn solve_p1(adapters: &[i64]) -> Result<usize, ChainError> {
+    let histogram = differences(adapters, Tolerance::default())?;
+    let count = |diff| histogram.get(&diff).copied().unwrap_or(0);
+    Ok(count(1) * count(3))
+}
+
+pub fn solve_p2(adapters: &[i64]) -> BigUint {
+    arrangements(adapters, Tolerance::default())
 }
 
 fn main() {
-    let lines = read_lines("src/inputs/day_10.txt").unwrap().flatten();
+    let lines = read_lines("src/inputs/day_10.txt")
+        .unwrap()
+        .map_while(Result::ok);
 
     let mut adapters = lines.map(|v| v.parse::<i64>().unwrap()).collect::<Vec<_>>();
     adapters.sort();
 
-    println!("Part 1: {:?}", solve_p1(&adapters));
-    println!("Part 2: {:?}", solve_p2(&adapters));
+    // `day_10 --min N --max N` changes the tolerance and prints the whole
+    // difference histogram, `day_10 enumerate|sample [N]` lists the first N
+    // or N random arrangements and `day_10 mandatory` the adapters in all
+    let args = std::env::args().collect::<Vec<_>>();
+    let arg = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args[i + 1].parse::<i64>().unwrap())
+    };
+    let default = Tolerance::default();
+    let tolerance = Tolerance {
+        min: arg("--min").unwrap_or(default.min),
+        max: arg("--max").unwrap_or(default.max),
+    };
+    let format = |adapters: Vec<i64>| {
+        adapters
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let n = args
+        .get(2)
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(1);
+    match args.get(1).map(String::as_str) {
+        Some("enumerate") => {
+            let chain = Chain::new(&adapters, tolerance);
+            for arrangement in chain.iter().take(n) {
+                println!("{}", format(arrangement));
+            }
+            return;
+        }
+        Some("sample") => {
+            let chain = Chain::new(&adapters, tolerance);
+            let mut rng = rand::thread_rng();
+            for _ in 0..n {
+                match chain.sample(&mut rng) {
+                    Some(arrangement) => println!("{}", format(arrangement)),
+                    None => println!("No arrangements"),
+                }
+            }
+            return;
+        }
+        Some("mandatory") => {
+            let chain = Chain::new(&adapters, tolerance);
+            let mandatory = chain.mandatory();
+            println!(
+                "{} of {} adapters in all {} arrangements",
+                mandatory.len(),
+                adapters.len(),
+                chain.count()
+            );
+            println!("{}", format(mandatory));
+            return;
+        }
+        _ => {}
+    }
+    if tolerance != default {
+        match differences(&adapters, tolerance) {
+            Ok(histogram) => {
+                for (diff, count) in histogram {
+                    println!("{}: {}", diff, count);
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+        println!("Arrangements: {}", arrangements(&adapters, tolerance));
+        return;
+    }
+
+    match solve_p1(&adapters) {
+        Ok(result) => println!("Part 1: {:?}", result),
+        Err(e) => println!("Part 1: {}", e),
+    }
+    println!("Part 2: {}", solve_p2(&adapters));
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    static SMALL: [i64; 11] = [16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4];
+    static LARGE: [i64; 31] = [
+        28, 33, 18, 42, 31, 14, 46, 20, 48, 47, 24, 23, 49, 45, 19, 38, 39, 11, 1, 32, 25, 35, 8,
+        17, 7, 9, 4, 2, 34, 10, 3,
+    ];
+
     #[test]
     fn example_p1_a() {
-        let mut adapters = vec![16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4];
+        let mut adapters = SMALL.to_vec();
         adapters.sort();
 
-        assert_eq!(solve_p1(&adapters), 35);
+        assert_eq!(solve_p1(&adapters), Ok(35));
     }
 
     #[test]
     fn example_p1_b() {
-        let mut adapters = vec![
-            28, 33, 18, 42, 31, 14, 46, 20, 48, 47, 24, 23, 49, 45, 19, 38, 39, 11, 1, 32, 25, 35,
-            8, 17, 7, 9, 4, 2, 34, 10, 3,
-        ];
+        let mut adapters = LARGE.to_vec();
         adapters.sort();
 
-        assert_eq!(solve_p1(&adapters), 220);
+        assert_eq!(solve_p1(&adapters), Ok(220));
     }
 
     #[test]
     fn example_p2_a() {
-        let mut adapters = vec![16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4];
+        let mut adapters = SMALL.to_vec();
         adapters.sort();
 
-        assert_eq!(solve_p2(&adapters), 8);
+        assert_eq!(solve_p2(&adapters), BigUint::from(8u32));
     }
 
     #[test]
     fn example_p2_b() {
-        let mut adapters = vec![
-            28, 33, 18, 42, 31, 14, 46, 20, 48, 47, 24, 23, 49, 45, 19, 38, 39, 11, 1, 32, 25, 35,
-            8, 17, 7, 9, 4, 2, 34, 10, 3,
-        ];
+        let mut adapters = LARGE.to_vec();
         adapters.sort();
 
-        assert_eq!(solve_p2(&adapters), 19208);
+        assert_eq!(solve_p2(&adapters), BigUint::from(19208u32));
+    }
+
+    #[test]
+    fn difference_histogram() {
+        let histogram = differences(&SMALL, Tolerance::default()).unwrap();
+        assert_eq!(
+            histogram.into_iter().collect::<Vec<_>>(),
+            vec![(1, 7), (3, 5)]
+        );
+
+        // No 1-jolt gaps is a product of zero, not a panic
+        assert_eq!(solve_p1(&[3, 6, 9]), Ok(0));
+        assert_eq!(
+            solve_p1(&[1, 2, 6]),
+            Err(ChainError::Gap { from: 2, to: 6 })
+        );
+    }
+
+    #[test]
+    fn other_tolerances() {
+        let tolerance = Tolerance { min: 2, max: 3 };
+        assert_eq!(
+            differences(&SMALL, tolerance),
+            Err(ChainError::Gap { from: 0, to: 1 })
+        );
+        assert_eq!(arrangements(&[2, 3, 5], tolerance), BigUint::from(2u32));
+
+        let tolerance = Tolerance { min: 1, max: 4 };
+        assert_eq!(arrangements(&[1, 2, 3, 4], tolerance), BigUint::from(8u32));
+    }
+
+    #[test]
+    fn counts_past_64_bits() {
+        // Tribonacci numbers: 1, 1, 2, 4, 7, 13, ...
+        let adapters = (1..=100).collect::<Vec<_>>();
+        assert_eq!(
+            solve_p2(&adapters).to_string(),
+            "180396380815100901214157639"
+        );
+    }
+
+    #[test]
+    fn enumerate_arrangements() {
+        let chain = Chain::new(&SMALL, Tolerance::default());
+        assert_eq!(
+            chain.iter().collect::<Vec<_>>(),
+            vec![
+                vec![1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19],
+                vec![1, 4, 5, 6, 7, 10, 12, 15, 16, 19],
+                vec![1, 4, 5, 7, 10, 11, 12, 15, 16, 19],
+                vec![1, 4, 5, 7, 10, 12, 15, 16, 19],
+                vec![1, 4, 6, 7, 10, 11, 12, 15, 16, 19],
+                vec![1, 4, 6, 7, 10, 12, 15, 16, 19],
+                vec![1, 4, 7, 10, 11, 12, 15, 16, 19],
+                vec![1, 4, 7, 10, 12, 15, 16, 19],
+            ]
+        );
+
+        let chain = Chain::new(&LARGE, Tolerance::default());
+        assert_eq!(chain.iter().count(), 19208);
+
+        // A dead end at 5 is never stepped on, and nothing connects with 1
+        let chain = Chain::new(&[2, 3, 5], Tolerance { min: 2, max: 3 });
+        assert_eq!(
+            chain.iter().collect::<Vec<_>>(),
+            vec![vec![2, 5], vec![3, 5]]
+        );
+        assert_eq!(
+            Chain::new(&[1, 5], Tolerance::default()).iter().next(),
+            None
+        );
+    }
+
+    #[test]
+    fn sample_arrangements() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        use std::collections::HashMap;
+
+        let chain = Chain::new(&SMALL, Tolerance::default());
+        let mut rng = StdRng::seed_from_u64(10);
+        let mut seen = HashMap::new();
+        for _ in 0..8000 {
+            *seen.entry(chain.sample(&mut rng).unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(seen.len(), 8);
+        for (arrangement, count) in seen {
+            assert!(chain.iter().any(|a| a == arrangement));
+            assert!((800..1200).contains(&count), "{:?}", (arrangement, count));
+        }
+
+        // Past 64 bits every sample is still a valid chain
+        let adapters = (1..=100).collect::<Vec<_>>();
+        let chain = Chain::new(&adapters, Tolerance::default());
+        let sample = chain.sample(&mut rng).unwrap();
+        assert!(sample.windows(2).all(|p| (1..=3).contains(&(p[1] - p[0]))));
+        assert!(sample[0] <= 3 && sample.last() == Some(&100));
+
+        assert_eq!(
+            Chain::new(&[1, 5], Tolerance::default()).sample(&mut rng),
+            None
+        );
+    }
+
+    #[test]
+    fn mandatory_adapters() {
+        let chain = Chain::new(&SMALL, Tolerance::default());
+        assert_eq!(chain.mandatory(), vec![1, 4, 7, 10, 12, 15, 16, 19]);
+
+        // 1 can't be used and 5 is the only way to the device
+        let chain = Chain::new(&[1, 2, 3, 5], Tolerance { min: 2, max: 3 });
+        assert_eq!(chain.mandatory(), vec![5]);
+
+        assert_eq!(
+            Chain::new(&[1, 5], Tolerance::default()).mandatory(),
+            Vec::<i64>::new()
+        );
     }
 }
@@ -0,0 +1,99 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+// Record writers shared by the puzzles that export data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    JsonLines,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(Format::JsonLines),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("Unknown export format {:?}", s)),
+        }
+    }
+}
+
+impl Format {
+    pub fn write<W: Write, R: Serialize>(&self, mut out: W, records: &[R]) -> io::Result<()> {
+        match self {
+            Format::JsonLines => {
+                for record in records {
+                    serde_json::to_writer(&mut out, record)?;
+                    writeln!(out)?;
+                }
+                Ok(())
+            }
+            Format::Csv => write_csv(out, records),
+        }
+    }
+}
+
+// Nested values become dotted columns, e.g. `hgt.cm`. Columns follow the order
+// fields are serialized in, each first seen in any record, so rows missing a
+// column leave it empty.
+fn write_csv<W: Write, R: Serialize>(mut out: W, records: &[R]) -> io::Result<()> {
+    let rows = records
+        .iter()
+        .map(|record| {
+            let mut row = vec![];
+            flatten("", serde_json::to_value(record)?, &mut row);
+            Ok(row)
+        })
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    let mut columns = vec![];
+    for (column, _) in rows.iter().flatten() {
+        if !columns.contains(column) {
+            columns.push(column.clone());
+        }
+    }
+
+    let header = columns.iter().map(|c| escape(c)).collect::<Vec<_>>();
+    writeln!(out, "{}", header.join(","))?;
+    for row in rows {
+        let row = row.into_iter().collect::<HashMap<_, _>>();
+        let cells = columns
+            .iter()
+            .map(|c| row.get(c).map(|v| escape(v)).unwrap_or_default())
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", cells.join(","))?;
+    }
+    Ok(())
+}
+
+fn flatten(prefix: &str, value: Value, row: &mut Vec<(String, String)>) {
+    let key = |k: &str| {
+        if prefix.is_empty() {
+            k.to_string()
+        } else {
+            format!("{}.{}", prefix, k)
+        }
+    };
+    match value {
+        Value::Object(map) => map.into_iter().for_each(|(k, v)| flatten(&key(&k), v, row)),
+        Value::Array(values) => values
+            .into_iter()
+            .enumerate()
+            .for_each(|(i, v)| flatten(&key(&i.to_string()), v, row)),
+        Value::Null => row.push((prefix.to_string(), String::new())),
+        Value::String(s) => row.push((prefix.to_string(), s)),
+        other => row.push((prefix.to_string(), other.to_string())),
+    }
+}
+
+fn escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
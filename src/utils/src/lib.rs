@@ -1,3 +1,5 @@
+pub mod export;
+
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;